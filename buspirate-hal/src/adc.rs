@@ -0,0 +1,63 @@
+//! Module `adc` provides an implementation of the one-shot ADC trait defined
+//! by `embedded-hal`.
+
+use buspirate::bitbang::BitBang;
+use embedded_hal::adc;
+use embedded_hal::serial;
+
+/// `BusPirateAdc` implements the `OneShot` trait from the `embedded_hal::adc`
+/// module.
+///
+/// This type wraps a Bus Pirate in "binary bit-bang" mode (as implemented in
+/// the `buspirate` crate) and takes samples from the Bus Pirate's ADC probe
+/// pin. The samples are raw 10-bit readings; use `buspirate::bitbang::adc_to_mv`
+/// to convert them to millivolts.
+pub struct BusPirateAdc<TX: serial::Write<u8>, RX: serial::Read<u8>> {
+    bp: BitBang<TX, RX>,
+}
+
+impl<TX, RX> BusPirateAdc<TX, RX>
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+{
+    /// `new` wraps a given Bus Pirate bit-bang mode object to implement the
+    /// one-shot ADC trait.
+    ///
+    /// ```rust,ignore
+    /// let bp_bb = BusPirate::new(tx, rx).to_bitbang()?;
+    /// let hal_adc = BusPirateAdc::new(bp_bb);
+    /// ```
+    pub fn new(bp_bb: BitBang<TX, RX>) -> BusPirateAdc<TX, RX> {
+        BusPirateAdc { bp: bp_bb }
+    }
+}
+
+/// `AdcChannel` represents the Bus Pirate's ADC probe pin, which is its only
+/// ADC input.
+pub struct AdcChannel;
+
+impl<TX, RX> adc::Channel<BusPirateAdc<TX, RX>> for AdcChannel
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+{
+    type ID = u8;
+
+    fn channel() -> u8 {
+        0
+    }
+}
+
+impl<TX, RX, TXErr, RXErr> adc::OneShot<BusPirateAdc<TX, RX>, u16, AdcChannel>
+    for BusPirateAdc<TX, RX>
+where
+    TX: serial::Write<u8, Error = TXErr>,
+    RX: serial::Read<u8, Error = RXErr>,
+{
+    type Error = buspirate::Error<TXErr, RXErr>;
+
+    fn read(&mut self, _pin: &mut AdcChannel) -> nb::Result<u16, Self::Error> {
+        self.bp.measure_voltage().map_err(nb::Error::Other)
+    }
+}
@@ -23,4 +23,7 @@
 
 extern crate embedded_hal;
 
+pub mod adc;
+pub mod prelude;
+pub mod pwm;
 pub mod spi;
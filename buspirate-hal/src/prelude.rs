@@ -0,0 +1,12 @@
+//! Module `prelude` re-exports the HAL implementation types from this crate,
+//! along with the prelude of the `buspirate` crate, so that they can all be
+//! imported at once:
+//!
+//! ```rust,ignore
+//! use buspirate_hal::prelude::*;
+//! ```
+
+pub use crate::adc::{AdcChannel, BusPirateAdc};
+pub use crate::pwm::BusPiratePwm;
+pub use crate::spi::BusPirateSPI;
+pub use buspirate::prelude::*;
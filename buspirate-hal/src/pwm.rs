@@ -0,0 +1,94 @@
+//! Module `pwm` provides an implementation of the PWM pin trait defined by
+//! `embedded-hal`.
+
+use buspirate::bitbang::{BitBang, PwmPrescaler};
+use embedded_hal::serial;
+
+/// `BusPiratePwm` implements the `PwmPin` trait from `embedded_hal`.
+///
+/// This type wraps a Bus Pirate in "binary bit-bang" mode (as implemented in
+/// the `buspirate` crate) and generates a pulse-width modulated signal on the
+/// Bus Pirate's AUX pin. The Bus Pirate has only one PWM output, so there is
+/// only one channel.
+///
+/// The signal frequency is fixed when calling `BusPiratePwm::new`, and is
+/// 16MHz divided by the prescaler and by `period + 1`. That allows
+/// frequencies from below 1Hz up to several megahertz, though the duty cycle
+/// resolution is coarser at higher frequencies because the maximum duty is
+/// `period + 1`, which keeps the pin high for the whole cycle. (With a period
+/// of 65535 the maximum duty is also 65535, since a larger duty can't be
+/// represented.)
+///
+/// The `PwmPin` trait methods cannot return errors, so any communication
+/// errors while changing the PWM settings are ignored.
+pub struct BusPiratePwm<TX: serial::Write<u8>, RX: serial::Read<u8>> {
+    bp: BitBang<TX, RX>,
+    prescaler: PwmPrescaler,
+    period: u16,
+    duty: u16,
+    enabled: bool,
+}
+
+impl<TX, RX> BusPiratePwm<TX, RX>
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+{
+    /// `new` wraps a given Bus Pirate bit-bang mode object to implement the
+    /// PWM pin trait, with the given timer prescaler and period.
+    ///
+    /// The PWM output is initially disabled, with a duty of zero.
+    ///
+    /// ```rust,ignore
+    /// let bp_bb = BusPirate::new(tx, rx).to_bitbang()?;
+    /// let hal_pwm = BusPiratePwm::new(bp_bb, PwmPrescaler::PwmPrescaler1, 15999); // 1kHz
+    /// ```
+    pub fn new(
+        bp_bb: BitBang<TX, RX>,
+        prescaler: PwmPrescaler,
+        period: u16,
+    ) -> BusPiratePwm<TX, RX> {
+        BusPiratePwm {
+            bp: bp_bb,
+            prescaler,
+            period,
+            duty: 0,
+            enabled: false,
+        }
+    }
+}
+
+impl<TX, RX> embedded_hal::PwmPin for BusPiratePwm<TX, RX>
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+{
+    type Duty = u16;
+
+    fn disable(&mut self) {
+        let _ = self.bp.clear_pwm();
+        self.enabled = false;
+    }
+
+    fn enable(&mut self) {
+        let _ = self.bp.setup_pwm(self.prescaler, self.duty, self.period);
+        self.enabled = true;
+    }
+
+    fn get_duty(&self) -> u16 {
+        self.duty
+    }
+
+    fn get_max_duty(&self) -> u16 {
+        // Each cycle lasts period + 1 timer counts.
+        self.period.saturating_add(1)
+    }
+
+    fn set_duty(&mut self, duty: u16) {
+        let max = self.get_max_duty();
+        self.duty = if duty > max { max } else { duty };
+        if self.enabled {
+            let _ = self.bp.setup_pwm(self.prescaler, self.duty, self.period);
+        }
+    }
+}
@@ -12,6 +12,7 @@ use embedded_hal::blocking::spi;
 /// calling `BusPirateSPI::new`.
 pub struct BusPirateSPI<BP: buspirate::spi::Comms> {
     bp: BP,
+    manual_cs: bool,
 }
 
 impl<BP, Error> BusPirateSPI<BP>
@@ -31,18 +32,99 @@ where
     /// You can then pass the resulting `BusPirateSPI` object to a HAL driver
     /// that expects to recieve a blocking SPI implementation.
     pub fn new(bp_spi: BP) -> BusPirateSPI<BP> {
-        BusPirateSPI { bp: bp_spi }
+        BusPirateSPI {
+            bp: bp_spi,
+            manual_cs: true,
+        }
     }
 }
 
-impl<BP, Error> spi::Transfer<u8> for BusPirateSPI<BP>
+impl<BP, Error> BusPirateSPI<BP>
+where
+    BP: buspirate::spi::ChipSelect<Error = Error>,
+{
+    /// `set_manual_cs` selects whether the caller controls the chip select
+    /// signal.
+    ///
+    /// In manual mode, which is the default, `Transfer` and `Write` never
+    /// change chip select, so the caller can keep it active across several
+    /// calls using `chip_select` or `transaction_cs`. Otherwise, each
+    /// `Transfer` and `Write` call activates chip select for its own
+    /// duration only, for drivers that expect the SPI implementation to
+    /// handle it.
+    pub fn set_manual_cs(&mut self, manual: bool) {
+        self.manual_cs = manual;
+    }
+
+    /// `chip_select` sets the state of the chip select signal, with `true`
+    /// representing the active (electrically low) state.
+    pub fn chip_select(&mut self, active: bool) -> Result<(), Error> {
+        self.bp.chip_select(active)
+    }
+
+    /// `transaction_cs` activates the chip select signal, calls the given
+    /// function, and then deactivates the chip select signal again.
+    ///
+    /// This allows a sequence of `Transfer` and `Write` operations to be
+    /// performed within a single activation of chip select, as required by
+    /// many devices with multi-part commands. Chip select is deactivated even
+    /// if the given function fails, in which case the function's error is
+    /// returned.
+    pub fn transaction_cs<R, F>(&mut self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<R, Error>,
+    {
+        buspirate::spi::Comms::transaction_cs(self, f)
+    }
+}
+
+/// `BusPirateSPI` implements `Comms` and `ChipSelect` by passing each call on
+/// to the wrapped object, so that `transaction_cs` can be used with the HAL
+/// traits.
+impl<BP, Error> buspirate::spi::Comms for BusPirateSPI<BP>
 where
     BP: buspirate::spi::Comms<Error = Error>,
+{
+    type Error = Error;
+
+    fn transfer<'w>(&mut self, v: &'w mut [u8]) -> Result<&'w [u8], Error> {
+        self.bp.transfer(v)
+    }
+
+    fn transaction<'w>(
+        &mut self,
+        write_from: &'w [u8],
+        read_into: &'w mut [u8],
+        cs: bool,
+    ) -> Result<(), Error> {
+        self.bp.transaction(write_from, read_into, cs)
+    }
+
+    fn write_only(&mut self, write_from: &[u8], cs: bool) -> Result<(), Error> {
+        self.bp.write_only(write_from, cs)
+    }
+}
+
+impl<BP, Error> buspirate::spi::ChipSelect for BusPirateSPI<BP>
+where
+    BP: buspirate::spi::ChipSelect<Error = Error>,
+{
+    fn chip_select(&mut self, active: bool) -> Result<(), Error> {
+        self.bp.chip_select(active)
+    }
+}
+
+impl<BP, Error> spi::Transfer<u8> for BusPirateSPI<BP>
+where
+    BP: buspirate::spi::ChipSelect<Error = Error>,
 {
     type Error = BP::Error;
 
     fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
-        self.bp.transfer(words)
+        if self.manual_cs {
+            return self.bp.transfer(words);
+        }
+        self.bp.transaction_cs(move |bp| bp.transfer(words))
     }
 }
 
@@ -53,7 +135,6 @@ where
     type Error = BP::Error;
 
     fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
-        let mut empty: [u8; 0] = [];
-        self.bp.transaction(words, &mut empty[..], false)
+        self.bp.write_only(words, !self.manual_cs)
     }
 }
@@ -0,0 +1,66 @@
+//! Tests of the chip select handling in `BusPirateSPI`, checking the exact
+//! bytes sent to a mock Bus Pirate.
+
+use buspirate::spi::SPI;
+use buspirate::test_util::{MockChannel, MockRx, MockTx};
+use buspirate::BusPirate;
+use buspirate_hal::spi::BusPirateSPI;
+use embedded_hal::blocking::spi::{Transfer, Write};
+
+/// `spi` enters SPI mode using the given mock, and then forgets the bytes
+/// written while doing so.
+fn spi(mock: &mut MockChannel) -> BusPirateSPI<SPI<MockTx, MockRx>> {
+    mock.reply_after(13, b"BBIO1").reply_after(1, b"SPI1");
+    let (tx, rx) = mock.split();
+    let mut spi = BusPirate::new(tx, rx)
+        .to_bitbang()
+        .unwrap()
+        .to_spi()
+        .unwrap();
+    spi.forget_reset();
+    mock.take_written();
+    BusPirateSPI::new(spi)
+}
+
+#[test]
+fn manual_cs() {
+    let mut mock = MockChannel::new();
+    let mut hal = spi(&mut mock);
+
+    mock.reply(&[0x01, 0xa1, 0xa2]);
+    let mut buf = [0x12, 0x34];
+    assert_eq!(hal.transfer(&mut buf).unwrap(), &[0xa1, 0xa2]);
+    mock.assert_written(&[0b00010001, 0x12, 0x34]);
+
+    // The Bus Pirate doesn't control chip select for the write either.
+    mock.reply(&[0x01]);
+    hal.write(&[0x56]).unwrap();
+    mock.assert_written(&[0b00000101, 0, 1, 0, 0, 0x56]);
+}
+
+#[test]
+fn automatic_cs() {
+    let mut mock = MockChannel::new();
+    let mut hal = spi(&mut mock);
+    hal.set_manual_cs(false);
+
+    mock.reply(&[0x01, 0x01, 0xa1, 0xa2, 0x01]);
+    let mut buf = [0x12, 0x34];
+    assert_eq!(hal.transfer(&mut buf).unwrap(), &[0xa1, 0xa2]);
+    mock.assert_written(&[0b00000010, 0b00010001, 0x12, 0x34, 0b00000011]);
+
+    // The Bus Pirate activates chip select for the duration of the write.
+    mock.reply(&[0x01]);
+    hal.write(&[0x56]).unwrap();
+    mock.assert_written(&[0b00000100, 0, 1, 0, 0, 0x56]);
+}
+
+#[test]
+fn transaction_cs() {
+    let mut mock = MockChannel::new();
+    let mut hal = spi(&mut mock);
+
+    mock.reply(&[0x01, 0x01, 0x01, 0x01]);
+    hal.transaction_cs(|hal| hal.write(&[0x56])).unwrap();
+    mock.assert_written(&[0b00000010, 0b00000101, 0, 1, 0, 0, 0x56, 0b00000011]);
+}
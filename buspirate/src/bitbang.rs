@@ -12,16 +12,53 @@
 
 use crate::low;
 use crate::BusPirate;
+use crate::CloseError;
 use crate::Error;
+use crate::ModeMarker;
+use crate::TransitionError;
+#[cfg(feature = "bitbang-gpio")]
+use core::convert::TryFrom;
+use embedded_hal::blocking::delay::DelayMs;
 use embedded_hal::serial;
 
+/// `ADC_FULL_SCALE_MV` is the voltage, in millivolts, that corresponds to the
+/// maximum reading of the Bus Pirate's analog-to-digital converter.
+///
+/// In the v3.6 schematic the ADC probe pin reaches the microcontroller through
+/// a resistor divider that halves its voltage, and the ADC itself measures
+/// against the 3.3V supply. The 10-bit range therefore spans 0V to 6.6V.
+pub const ADC_FULL_SCALE_MV: u32 = 6600;
+
+/// `ADC_PIPELINE` is the maximum number of ADC sample requests that
+/// `BitBang::measure_voltages` sends ahead of the responses it has read.
+///
+/// Each request produces two bytes of response, so this limits how much the
+/// Bus Pirate's responses can build up in the host's receive buffer.
+pub const ADC_PIPELINE: usize = 8;
+
+/// `adc_to_mv` converts a raw 10-bit reading from the Bus Pirate's ADC into
+/// millivolts.
+///
+/// For an unmodified Bus Pirate, pass `ADC_FULL_SCALE_MV` as `full_scale_mv`.
+/// Boards whose ADC divider or reference voltage has been changed can pass
+/// their own full-scale voltage instead.
+pub fn adc_to_mv(raw: u16, full_scale_mv: u32) -> u32 {
+    u32::from(raw) * full_scale_mv / 1024
+}
+
 /// `BitBang` represents a Bus Pirate device in "binary bit-bang" mode.
 ///
 /// This mode serves both as an interface to directly control some of the
 /// Bus Pirate's pins and as an intermediate step to reach the higher-level
 /// protocol modes.
+///
+/// If a `BitBang` object is dropped without calling `close`, the Bus Pirate is
+/// reset back into terminal mode on a best-effort basis. Call `forget_reset`
+/// to prevent that.
 pub struct BitBang<TX: serial::Write<u8>, RX: serial::Read<u8>> {
     pub(crate) ch: low::Channel<TX, RX>,
+    #[cfg(feature = "bitbang-gpio")]
+    pins: PinLevels,
 }
 
 impl<TX, RX, TXErr, RXErr> BitBang<TX, RX>
@@ -29,18 +66,539 @@ where
     TX: serial::Write<u8, Error = TXErr>,
     RX: serial::Read<u8, Error = RXErr>,
 {
+    pub(crate) fn new(ch: low::Channel<TX, RX>) -> Self {
+        Self {
+            ch,
+            // Entering bit-bang mode resets all of the pins to low.
+            #[cfg(feature = "bitbang-gpio")]
+            pins: PinLevels::default(),
+        }
+    }
+
     /// `close` resets the Bus Pirate back into normal terminal mode, exiting
     /// binary bitbang mode.
     pub fn close(self) -> Result<BusPirate<TX, RX>, Error<TXErr, RXErr>> {
-        crate::close_handshake(self.ch)
+        crate::close_handshake(self.ch, crate::Mode::BitBang)
+    }
+
+    /// `close_checked` is a variant of `close` that confirms that the Bus
+    /// Pirate has actually returned to terminal mode.
+    ///
+    /// After sending the reset command, this waits for the given number of
+    /// milliseconds, using the given delay implementation, to allow the Bus
+    /// Pirate to restart. It then checks that the Bus Pirate is responding,
+    /// in the same way as `BusPirate::ping`. This is much slower than
+    /// `close`, but is useful for a program that must leave the Bus Pirate in
+    /// a known state.
+    ///
+    /// If the Bus Pirate doesn't respond, the error from `ping` is returned
+    /// in a `CloseError` whose `previous` is the `BusPirate` object, so
+    /// that the serial channels are not lost. The same applies if sending the
+    /// reset command fails, since the Bus Pirate may have received it anyway.
+    pub fn close_checked<D: DelayMs<u16>>(
+        self,
+        delay: &mut D,
+        ms: u16,
+    ) -> Result<BusPirate<TX, RX>, CloseError<TX, RX, TXErr, RXErr>> {
+        crate::close_checked_handshake(self.ch, crate::Mode::BitBang, delay, ms)
+    }
+
+    /// `into_inner` returns the serial transmit and receive objects wrapped by
+    /// this object, without resetting the Bus Pirate.
+    ///
+    /// The Bus Pirate is left in bit-bang mode, for a caller that intends to
+    /// continue communicating with it directly or pass it to another library.
+    /// Any data already received but not yet read by this object is lost,
+    /// including a byte held back by `has_pending`. To instead return the
+    /// Bus Pirate to terminal mode, use `close`.
+    pub fn into_inner(self) -> (TX, RX) {
+        self.ch.into_parts()
+    }
+
+    /// `forget_reset` prevents the Bus Pirate from being reset when this
+    /// object, or any mode object later derived from it, is dropped.
+    ///
+    /// Use this to intentionally leave the Bus Pirate in its current mode
+    /// after the program exits.
+    pub fn forget_reset(&mut self) {
+        self.ch.tx.reset_on_drop = false;
+    }
+
+    /// `flush` blocks until all data previously sent to the Bus Pirate has
+    /// been transmitted by the underlying serial implementation.
+    ///
+    /// Methods that wait for a response from the Bus Pirate already flush
+    /// automatically, so this is needed only when pipelining commands.
+    pub fn flush(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        self.ch.flush()
+    }
+
+    /// `has_pending` returns true if the Bus Pirate has sent data that has
+    /// not yet been read, without blocking to wait for any.
+    ///
+    /// If a byte is available it is kept and returned by the next method that
+    /// reads from the Bus Pirate, so checking for pending data never loses
+    /// any. This allows an event loop to poll for data without committing to
+    /// a blocking read.
+    pub fn has_pending(&mut self) -> Result<bool, Error<TXErr, RXErr>> {
+        self.ch.has_pending()
+    }
+
+    /// `set_pins` sets the levels of all of the Bus Pirate's output pins at
+    /// once, returning the levels of the pins as read back by the Bus Pirate
+    /// immediately afterwards.
+    #[cfg(feature = "bitbang-gpio")]
+    pub fn set_pins(&mut self, levels: PinLevels) -> Result<PinLevels, Error<TXErr, RXErr>> {
+        self.ch.write(levels.command_byte())?;
+        self.ch.flush_command()?;
+        let got = self.ch.read()?;
+        self.pins = levels;
+        PinLevels::try_from(got).map_err(|_| Error::Protocol)
+    }
+
+    /// `update_pins` changes the levels of some of the Bus Pirate's output
+    /// pins, leaving the others as they were most recently set through this
+    /// object, and returns the levels read back as for `set_pins`.
+    ///
+    /// The given function receives the current levels and returns the new
+    /// levels, so that a single pin can be changed without restating the
+    /// others:
+    ///
+    /// ```rust,ignore
+    /// bb.update_pins(|pins| pins.with(PinId::Mosi, true).toggle(PinId::Clk))?;
+    /// ```
+    #[cfg(feature = "bitbang-gpio")]
+    pub fn update_pins<F>(&mut self, f: F) -> Result<PinLevels, Error<TXErr, RXErr>>
+    where
+        F: FnOnce(PinLevels) -> PinLevels,
+    {
+        self.set_pins(f(self.pins))
+    }
+
+    /// `config_snapshot` returns the settings most recently applied to this
+    /// bit-bang mode object, for display or debugging.
+    ///
+    /// The snapshot reflects only the settings changed through this object
+    /// since entering bit-bang mode. It does not query the Bus Pirate; use
+    /// `read_peripherals` for that.
+    #[cfg(feature = "bitbang-gpio")]
+    pub fn config_snapshot(&self) -> ConfigSnapshot {
+        ConfigSnapshot { pins: self.pins }
+    }
+
+    /// `read_peripherals` reads back the current states of the Bus Pirate's
+    /// general peripherals, as reported by the Bus Pirate itself.
+    ///
+    /// This can be used to verify that the power supplies, pull-up resistors,
+    /// AUX and chip select pins are in the expected states. The pin levels
+    /// most recently set in bit-bang mode are re-applied in order to read the
+    /// current states, so no pins are changed.
+    #[cfg(feature = "bitbang-gpio")]
+    pub fn read_peripherals(&mut self) -> Result<crate::peripherals::Config, Error<TXErr, RXErr>> {
+        let got = self.set_pins(self.pins)?;
+        Ok(crate::peripherals::Config {
+            power_supply: got.power_supply,
+            pull_ups: got.pull_ups,
+            aux: got.aux,
+            cs: got.cs,
+        })
+    }
+
+    /// `power_on` switches on the Bus Pirate's on-board power supplies,
+    /// leaving all of the other pins unchanged.
+    ///
+    /// This can be used to power up a target device before switching into
+    /// one of the protocol modes.
+    #[cfg(feature = "bitbang-gpio")]
+    pub fn power_on(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        self.set_pins(PinLevels {
+            power_supply: true,
+            ..self.pins
+        })?;
+        Ok(())
+    }
+
+    /// `power_off` switches off the Bus Pirate's on-board power supplies,
+    /// leaving all of the other pins unchanged.
+    #[cfg(feature = "bitbang-gpio")]
+    pub fn power_off(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        self.set_pins(PinLevels {
+            power_supply: false,
+            ..self.pins
+        })?;
+        Ok(())
+    }
+
+    /// `pullups_on` enables the Bus Pirate's on-board pull-up resistors,
+    /// leaving all of the other pins unchanged.
+    #[cfg(feature = "bitbang-gpio")]
+    pub fn pullups_on(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        self.set_pins(PinLevels {
+            pull_ups: true,
+            ..self.pins
+        })?;
+        Ok(())
+    }
+
+    /// `pullups_off` disables the Bus Pirate's on-board pull-up resistors,
+    /// leaving all of the other pins unchanged.
+    #[cfg(feature = "bitbang-gpio")]
+    pub fn pullups_off(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        self.set_pins(PinLevels {
+            pull_ups: false,
+            ..self.pins
+        })?;
+        Ok(())
+    }
+
+    /// `power_cycle` switches off the Bus Pirate's on-board power supplies,
+    /// waits for the given number of milliseconds using the given delay
+    /// implementation, and then switches them back on.
+    #[cfg(feature = "bitbang-gpio")]
+    pub fn power_cycle<D: DelayMs<u16>>(
+        &mut self,
+        delay: &mut D,
+        ms: u16,
+    ) -> Result<(), Error<TXErr, RXErr>> {
+        self.power_off()?;
+        delay.delay_ms(ms);
+        self.power_on()
+    }
+
+    /// `setup_pwm` starts generating a pulse-width modulated signal on the
+    /// Bus Pirate's AUX pin.
+    ///
+    /// The signal is generated by the Bus Pirate's microcontroller timer,
+    /// which counts at 16MHz divided by the given prescaler. `period` is the
+    /// value of the timer's period register, so each cycle lasts `period + 1`
+    /// timer counts, and `duty` is the number of counts in each cycle for
+    /// which the pin is high. For example, with `PwmPrescaler1`, a period of
+    /// 15999 and a duty of 8000 produce a 1kHz signal with a 50% duty cycle.
+    ///
+    /// The signal continues even after leaving bit-bang mode, until cleared
+    /// with `clear_pwm`.
+    pub fn setup_pwm(
+        &mut self,
+        prescaler: PwmPrescaler,
+        duty: u16,
+        period: u16,
+    ) -> Result<(), Error<TXErr, RXErr>> {
+        let prescaler = match prescaler {
+            PwmPrescaler::PwmPrescaler1 => 0b00,
+            PwmPrescaler::PwmPrescaler8 => 0b01,
+            PwmPrescaler::PwmPrescaler64 => 0b10,
+            PwmPrescaler::PwmPrescaler256 => 0b11,
+        };
+        self.ch.write(0b00010010)?;
+        self.ch.write(prescaler)?;
+        self.ch.write((duty >> 8) as u8)?; // MSB of duty cycle
+        self.ch.write(duty as u8)?; // LSB of duty cycle
+        self.ch.write((period >> 8) as u8)?; // MSB of period
+        self.ch.write(period as u8)?; // LSB of period
+        self.ch.flush_command()?;
+
+        match self.ch.read()? {
+            0x01 => Ok(()),
+            got => Err(Error::UnexpectedByte {
+                expected: 0x01,
+                got,
+            }),
+        }
+    }
+
+    /// `clear_pwm` stops generating the pulse-width modulated signal started
+    /// by `setup_pwm`.
+    pub fn clear_pwm(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        self.ch.simple_command(0b00010011)
+    }
+
+    /// `measure_voltage` takes a single sample of the voltage at the Bus
+    /// Pirate's ADC probe pin.
+    ///
+    /// The result is a raw 10-bit ADC reading, which can be converted to
+    /// millivolts using `adc_to_mv`.
+    pub fn measure_voltage(&mut self) -> Result<u16, Error<TXErr, RXErr>> {
+        self.ch.write(0b00010100)?;
+        self.ch.flush_command()?;
+        let hi = self.ch.read()?;
+        let lo = self.ch.read()?;
+        Ok(u16::from_be_bytes([hi, lo]))
+    }
+
+    /// `measure_voltages` takes a series of samples of the voltage at the Bus
+    /// Pirate's ADC probe pin, filling the given slice with raw 10-bit ADC
+    /// readings as for `measure_voltage`.
+    ///
+    /// Rather than waiting for each sample before requesting the next, this
+    /// keeps up to `ADC_PIPELINE` requests in flight, so that sampling isn't
+    /// slowed by the round trip to the Bus Pirate for each one. The sample
+    /// rate is then limited by the serial link, which must carry two bytes
+    /// per sample: at 115200 baud that is at most about 5700 samples per
+    /// second, and in practice somewhat less. The interval between samples
+    /// is not regular, so for a steady rate use `stream_voltage` instead.
+    pub fn measure_voltages(&mut self, into: &mut [u16]) -> Result<(), Error<TXErr, RXErr>> {
+        let len = into.len();
+        let mut sent = 0;
+        for (i, sample) in into.iter_mut().enumerate() {
+            let want = core::cmp::min(i + ADC_PIPELINE, len);
+            if sent < want {
+                while sent < want {
+                    self.ch.write(0b00010100)?;
+                    sent += 1;
+                }
+                self.ch.flush_command()?;
+            }
+            let hi = self.ch.read()?;
+            let lo = self.ch.read()?;
+            *sample = u16::from_be_bytes([hi, lo]);
+        }
+        Ok(())
+    }
+
+    /// `stream_voltage` starts continuous sampling of the voltage at the Bus
+    /// Pirate's ADC probe pin, returning an iterator over the raw samples.
+    ///
+    /// Each item is a raw 10-bit ADC reading, which can be converted to
+    /// millivolts using `adc_to_mv`. The Bus Pirate produces samples as fast
+    /// as the serial link allows, so the iterator can be combined with
+    /// adapters like `take` to collect a specific number of samples.
+    ///
+    /// Sampling continues until the `VoltageStream` is dropped, at which point
+    /// the Bus Pirate is told to stop and any samples still in transit are
+    /// discarded.
+    pub fn stream_voltage(&mut self) -> Result<VoltageStream<'_, TX, RX>, Error<TXErr, RXErr>> {
+        self.ch.write(0b00010101)?;
+        self.ch.flush()?;
+        Ok(VoltageStream { bb: self })
     }
 
     /// `to_spi` switches to SPI mode.
     ///
     /// This consumes the `BigBang` object. To retrieve it, call `to_bitbang`
     /// on the resulting SPI object to switch back to the bit-bang mode.
-    pub fn to_spi(self) -> Result<crate::spi::SPI<TX, RX>, Error<TXErr, RXErr>> {
-        let ch = crate::binary_mode_handshake(self.ch, 0b00000001, &crate::PROTO_SPI_VERSION_MSG)?;
-        Ok(crate::spi::SPI { ch: ch })
+    ///
+    /// If the Bus Pirate does not confirm the switch, the `BitBang` object is
+    /// returned inside the resulting `TransitionError` so that the caller may
+    /// retry. If the Bus Pirate responds with something other than the SPI
+    /// mode version string, the error is `ModeUnavailable`.
+    #[cfg(feature = "spi")]
+    pub fn to_spi(self) -> Result<crate::spi::SPI<TX, RX>, TransitionError<Self, TXErr, RXErr>> {
+        self.enter()
+    }
+
+    /// `to_spi_matching` is a variant of `to_spi` that uses the given function
+    /// to decide whether the Bus Pirate has confirmed the switch to SPI mode.
+    ///
+    /// The function is called with the four most recent bytes received from
+    /// the Bus Pirate and must return true if they are an acceptable SPI mode
+    /// version string. `to_spi` accepts only "SPI1", but a caller using
+    /// firmware that reports a different protocol version could, for example,
+    /// accept any version number:
+    ///
+    /// ```rust,ignore
+    /// let spi = bb.to_spi_matching(|got| got.starts_with(b"SPI"))?;
+    /// ```
+    #[cfg(feature = "spi")]
+    pub fn to_spi_matching<F>(
+        self,
+        accept: F,
+    ) -> Result<crate::spi::SPI<TX, RX>, TransitionError<Self, TXErr, RXErr>>
+    where
+        F: Fn(&[u8; 4]) -> bool,
+    {
+        self.enter_matching(accept)
+    }
+
+    /// `enter` switches to the protocol mode represented by type `M`, such as
+    /// `spi::SPI`.
+    ///
+    /// This is equivalent to the mode-specific methods like `to_spi`, but
+    /// allows the target mode to be chosen by a type parameter:
+    ///
+    /// ```rust,ignore
+    /// let spi: SPI<_, _> = bb.enter()?;
+    /// ```
+    pub fn enter<M: ModeMarker<TX, RX>>(self) -> Result<M, TransitionError<Self, TXErr, RXErr>> {
+        self.enter_matching(|got| got == &M::VERSION_MSG)
+    }
+
+    /// `enter_matching` implements `enter` and `to_spi_matching`, using the
+    /// given function to decide whether the Bus Pirate confirmed the switch.
+    fn enter_matching<M, F>(mut self, accept: F) -> Result<M, TransitionError<Self, TXErr, RXErr>>
+    where
+        M: ModeMarker<TX, RX>,
+        F: Fn(&[u8; 4]) -> bool,
+    {
+        match crate::binary_mode_handshake(&mut self.ch, M::ENTER_COMMAND, M::MODE, accept) {
+            Ok(()) => {
+                self.ch.transition(crate::Mode::BitBang, M::MODE);
+                Ok(M::from_channel(self.ch))
+            }
+            Err(error) => Err(TransitionError {
+                previous: self,
+                error,
+            }),
+        }
+    }
+}
+
+/// `VoltageStream` is an iterator over continuous voltage samples from the
+/// Bus Pirate's ADC probe pin, created by `BitBang::stream_voltage`.
+pub struct VoltageStream<'a, TX: serial::Write<u8>, RX: serial::Read<u8>> {
+    bb: &'a mut BitBang<TX, RX>,
+}
+
+impl<'a, TX, RX, TXErr, RXErr> Iterator for VoltageStream<'a, TX, RX>
+where
+    TX: serial::Write<u8, Error = TXErr>,
+    RX: serial::Read<u8, Error = RXErr>,
+{
+    type Item = Result<u16, Error<TXErr, RXErr>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut sample = [0_u8; 2];
+        for c in sample.iter_mut() {
+            match self.bb.ch.read_data() {
+                Ok(v) => *c = v,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        Some(Ok(u16::from_be_bytes(sample)))
+    }
+}
+
+impl<'a, TX: serial::Write<u8>, RX: serial::Read<u8>> Drop for VoltageStream<'a, TX, RX> {
+    fn drop(&mut self) {
+        // Any byte stops the sampling. We can't return errors from here, so
+        // this is just a best effort.
+        let ch = &mut self.bb.ch;
+        let _ = ch.write(0x00).and_then(|_| ch.flush());
+        let _ = ch.eat_rx_buffer();
+    }
+}
+
+/// `ConfigSnapshot` describes the settings most recently applied to a
+/// `BitBang` object, as returned by `BitBang::config_snapshot`.
+#[cfg(feature = "bitbang-gpio")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigSnapshot {
+    /// `pins` is the pin levels most recently requested using `set_pins` or
+    /// one of the methods based on it, which are initially all low.
+    pub pins: PinLevels,
+}
+
+/// `PwmPrescaler` describes how much the Bus Pirate's microcontroller clock is
+/// divided to produce the timer clock for pulse-width modulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PwmPrescaler {
+    PwmPrescaler1,
+    PwmPrescaler8,
+    PwmPrescaler64,
+    PwmPrescaler256,
+}
+
+/// `PinLevels` describes the states of the pins that can be controlled
+/// directly in bit-bang mode.
+///
+/// The same structure is used both to request new pin levels and to report
+/// the levels read back by the Bus Pirate.
+#[cfg(feature = "bitbang-gpio")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PinLevels {
+    pub power_supply: bool,
+    pub pull_ups: bool,
+    pub aux: bool,
+    pub mosi: bool,
+    pub clk: bool,
+    pub miso: bool,
+    pub cs: bool,
+}
+
+/// `PinId` identifies one of the pins whose level is described by
+/// `PinLevels`.
+#[cfg(feature = "bitbang-gpio")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinId {
+    PowerSupply,
+    PullUps,
+    Aux,
+    Mosi,
+    Clk,
+    Miso,
+    Cs,
+}
+
+#[cfg(feature = "bitbang-gpio")]
+impl PinLevels {
+    /// `get` returns the level of the given pin.
+    pub fn get(&self, pin: PinId) -> bool {
+        match pin {
+            PinId::PowerSupply => self.power_supply,
+            PinId::PullUps => self.pull_ups,
+            PinId::Aux => self.aux,
+            PinId::Mosi => self.mosi,
+            PinId::Clk => self.clk,
+            PinId::Miso => self.miso,
+            PinId::Cs => self.cs,
+        }
+    }
+
+    /// `with` returns a copy of these levels with the given pin set to the
+    /// given level.
+    pub fn with(mut self, pin: PinId, level: bool) -> Self {
+        match pin {
+            PinId::PowerSupply => self.power_supply = level,
+            PinId::PullUps => self.pull_ups = level,
+            PinId::Aux => self.aux = level,
+            PinId::Mosi => self.mosi = level,
+            PinId::Clk => self.clk = level,
+            PinId::Miso => self.miso = level,
+            PinId::Cs => self.cs = level,
+        }
+        self
+    }
+
+    /// `toggle` returns a copy of these levels with the level of the given
+    /// pin inverted.
+    pub fn toggle(self, pin: PinId) -> Self {
+        self.with(pin, !self.get(pin))
+    }
+
+    pub(crate) fn command_byte(&self) -> u8 {
+        0b10000000
+            | (self.power_supply as u8) << 6
+            | (self.pull_ups as u8) << 5
+            | (self.aux as u8) << 4
+            | (self.mosi as u8) << 3
+            | (self.clk as u8) << 2
+            | (self.miso as u8) << 1
+            | (self.cs as u8)
+    }
+}
+
+/// A `PinLevels` can be decoded from a pin state byte as returned by the Bus
+/// Pirate in bit-bang mode.
+///
+/// The Bus Pirate always sets the most significant bit of these bytes, so a
+/// byte without it is rejected, with the byte itself returned as the error.
+/// This helps to detect when the host has lost synchronization with the
+/// Bus Pirate's responses.
+#[cfg(feature = "bitbang-gpio")]
+impl TryFrom<u8> for PinLevels {
+    type Error = u8;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        if v & 0b10000000 == 0 {
+            return Err(v);
+        }
+        Ok(Self {
+            power_supply: v & (1 << 6) != 0,
+            pull_ups: v & (1 << 5) != 0,
+            aux: v & (1 << 4) != 0,
+            mosi: v & (1 << 3) != 0,
+            clk: v & (1 << 2) != 0,
+            miso: v & (1 << 1) != 0,
+            cs: v & 1 != 0,
+        })
     }
 }
@@ -0,0 +1,160 @@
+//! Module `dynamic` contains an alternative API that tracks the current Bus
+//! Pirate mode at runtime, rather than in the type system.
+//!
+//! The main API of this crate represents each mode as a separate type, so
+//! that calling an operation that is invalid in the current mode is a compile
+//! error. That is the best choice for most programs, but it can be awkward
+//! for scripting and interactive tools that switch modes in response to user
+//! input. `Device` instead wraps whichever mode object is active, and its
+//! methods return the `Request` error if called in the wrong mode.
+//!
+//! This module is available only when the `dynamic` feature is enabled.
+//!
+//! ```rust,ignore
+//! let mut dev = Device::new(tx, rx);
+//! dev.enter_spi()?;
+//! dev.spi_set_speed(spi::Speed::Speed1MHz)?;
+//! dev.spi_transfer(&mut buf)?;
+//! dev.close()?;
+//! ```
+
+use crate::bitbang::BitBang;
+use crate::spi;
+use crate::spi::Comms;
+use crate::BusPirate;
+use crate::Error;
+use embedded_hal::serial;
+
+/// `Device` represents a Bus Pirate device in whichever mode it is currently
+/// in, as tracked at runtime.
+pub enum Device<TX: serial::Write<u8>, RX: serial::Read<u8>> {
+    /// `Terminal` is the normal terminal mode, before entering any binary mode.
+    Terminal(BusPirate<TX, RX>),
+
+    /// `BitBang` is "binary bit-bang" mode.
+    BitBang(BitBang<TX, RX>),
+
+    /// `SPI` is SPI mode.
+    SPI(spi::SPI<TX, RX>),
+
+    /// `Lost` indicates that an earlier mode transition failed in a way that
+    /// did not allow recovering the serial channels. All methods return the
+    /// `Request` error in this state.
+    Lost,
+}
+
+impl<TX, RX, TXErr, RXErr> Device<TX, RX>
+where
+    TX: serial::Write<u8, Error = TXErr>,
+    RX: serial::Read<u8, Error = RXErr>,
+{
+    /// `Device::new` associates some serial channels with a new `Device`
+    /// object, initially in terminal mode.
+    pub fn new(tx: TX, rx: RX) -> Self {
+        Device::Terminal(BusPirate::new(tx, rx))
+    }
+
+    /// `enter_bitbang` switches to "binary bit-bang" mode from any other mode.
+    ///
+    /// If the device is already in bit-bang mode then this does nothing.
+    ///
+    /// If switching from terminal or SPI mode fails, the serial channels are
+    /// lost for good, because the mode objects don't return them on failure.
+    /// The device is then in the `Lost` state, and a new `Device` must be
+    /// created from new serial channels to continue.
+    pub fn enter_bitbang(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        *self = match core::mem::replace(self, Device::Lost) {
+            Device::Terminal(bp) => Device::BitBang(bp.to_bitbang()?),
+            Device::BitBang(bb) => Device::BitBang(bb),
+            Device::SPI(spi) => Device::BitBang(spi.to_bitbang()?),
+            Device::Lost => return Err(Error::Request),
+        };
+        Ok(())
+    }
+
+    /// `enter_spi` switches to SPI mode from any other mode, passing through
+    /// bit-bang mode if necessary.
+    ///
+    /// If the device is already in SPI mode then this does nothing.
+    pub fn enter_spi(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        if let Device::SPI(_) = self {
+            return Ok(());
+        }
+        self.enter_bitbang()?;
+        match core::mem::replace(self, Device::Lost) {
+            Device::BitBang(bb) => match bb.to_spi() {
+                Ok(spi) => {
+                    *self = Device::SPI(spi);
+                    Ok(())
+                }
+                Err(err) => {
+                    *self = Device::BitBang(err.previous);
+                    Err(err.error)
+                }
+            },
+            other => {
+                *self = other;
+                Err(Error::Request)
+            }
+        }
+    }
+
+    /// `close` resets the Bus Pirate back into normal terminal mode.
+    ///
+    /// If the device is already in terminal mode then this does nothing.
+    ///
+    /// As with `enter_bitbang`, if the reset fails then the serial channels
+    /// are lost for good and the device is left in the `Lost` state.
+    pub fn close(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        *self = match core::mem::replace(self, Device::Lost) {
+            Device::Terminal(bp) => Device::Terminal(bp),
+            Device::BitBang(bb) => Device::Terminal(bb.close()?),
+            Device::SPI(spi) => Device::Terminal(spi.close()?),
+            Device::Lost => return Err(Error::Request),
+        };
+        Ok(())
+    }
+
+    /// `spi_set_speed` calls `set_speed` on the SPI mode object, or returns
+    /// the `Request` error if the device is not in SPI mode.
+    pub fn spi_set_speed(&mut self, speed: spi::Speed) -> Result<(), Error<TXErr, RXErr>> {
+        self.spi()?.set_speed(speed)
+    }
+
+    /// `spi_set_config` calls `set_config` on the SPI mode object, or returns
+    /// the `Request` error if the device is not in SPI mode.
+    pub fn spi_set_config(&mut self, config: spi::Config) -> Result<(), Error<TXErr, RXErr>> {
+        self.spi()?.set_config(config)
+    }
+
+    /// `spi_chip_select` calls `chip_select` on the SPI mode object, or
+    /// returns the `Request` error if the device is not in SPI mode.
+    pub fn spi_chip_select(&mut self, active: bool) -> Result<(), Error<TXErr, RXErr>> {
+        self.spi()?.chip_select(active)
+    }
+
+    /// `spi_transfer` performs an SPI transfer of any length using
+    /// `Comms::transfer`, or returns the `Request` error if the device is not
+    /// in SPI mode.
+    pub fn spi_transfer<'w>(&mut self, v: &'w mut [u8]) -> Result<&'w [u8], Error<TXErr, RXErr>> {
+        self.spi()?.transfer(v)
+    }
+
+    /// `spi_write_then_read` calls `write_then_read` on the SPI mode object,
+    /// or returns the `Request` error if the device is not in SPI mode.
+    pub fn spi_write_then_read(
+        &mut self,
+        write_from: &[u8],
+        read_into: &mut [u8],
+        cs: bool,
+    ) -> Result<(), Error<TXErr, RXErr>> {
+        self.spi()?.write_then_read(write_from, read_into, cs)
+    }
+
+    fn spi(&mut self) -> Result<&mut spi::SPI<TX, RX>, Error<TXErr, RXErr>> {
+        match self {
+            Device::SPI(spi) => Ok(spi),
+            _ => Err(Error::Request),
+        }
+    }
+}
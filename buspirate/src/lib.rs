@@ -49,17 +49,39 @@
 //! ```rust
 //! let spi = bp.to_spi()?;
 //! ```
+//!
+//! Each protocol mode can be excluded from the build by disabling its Cargo
+//! feature, which is useful when this library is used from firmware with
+//! little room to spare. The `spi` feature enables the `spi` module and
+//! `BitBang::to_spi`, while the `bitbang-gpio` feature enables the direct pin
+//! control methods of `BitBang`, such as `set_pins`. Both are enabled by
+//! default. The handshakes for entering and leaving binary mode are always
+//! available.
 
 #![no_std]
 
+#[cfg(feature = "test-util")]
+extern crate std;
+
 pub mod bitbang;
+#[cfg(feature = "dynamic")]
+pub mod dynamic;
 mod low;
 pub mod peripherals;
+pub mod prelude;
+#[cfg(feature = "spi")]
 pub mod spi;
+#[cfg(feature = "test-util")]
+pub mod test_util;
 
+use embedded_hal::blocking::delay::DelayMs;
 use embedded_hal::serial;
 
 const PROTO_VERSION_MSG: [u8; 5] = ['B' as u8, 'B' as u8, 'I' as u8, 'O' as u8, '1' as u8];
+/// `LINK_CHECKS` is the number of extra version strings `verify_link` checks
+/// after entering bit-bang mode.
+const LINK_CHECKS: usize = 4;
+#[cfg(feature = "spi")]
 const PROTO_SPI_VERSION_MSG: [u8; 4] = ['S' as u8, 'P' as u8, 'I' as u8, '1' as u8];
 
 /// `BusPirate` represents a Bus Pirate device in its normal terminal mode, not
@@ -72,7 +94,14 @@ const PROTO_SPI_VERSION_MSG: [u8; 4] = ['S' as u8, 'P' as u8, 'I' as u8, '1' as
 /// ```rust
 /// let bb = bp.to_bitbang()?;
 /// ```
-#[derive(Debug, Clone)]
+///
+/// Each `BusPirate` object, and each of the mode objects derived from it,
+/// exclusively owns the serial channels it was created with. These types
+/// intentionally do not implement `Clone`, because two handles sharing a
+/// single serial port would interfere with each other's commands and
+/// responses. They are `Send` and `Sync` whenever the underlying serial
+/// types are, so a device can be moved to another thread as a whole.
+#[derive(Debug)]
 pub struct BusPirate<TX: serial::Write<u8>, RX: serial::Read<u8>> {
     ch: low::Channel<TX, RX>,
 }
@@ -94,6 +123,52 @@ where
         }
     }
 
+    /// `with_entry_params` adjusts the sequence used to enter "binary bit-bang"
+    /// mode, returning the modified `BusPirate` object.
+    ///
+    /// When entering bit-bang mode, the Bus Pirate is first sent `escapes`
+    /// newlines to escape from any menu or prompt in progress, and then reset.
+    /// Then up to `nulls` null bytes are sent until the Bus Pirate confirms that
+    /// it has entered binary mode. The defaults are 10 and 20 respectively, as
+    /// recommended by the Bus Pirate documentation.
+    ///
+    /// Some serial adapters need larger values to enter bit-bang mode
+    /// reliably, while on others smaller values can make entry faster. The
+    /// values are retained for subsequent transitions into bit-bang mode,
+    /// including from other binary modes.
+    pub fn with_entry_params(mut self, escapes: u8, nulls: u8) -> Self {
+        self.ch.escapes = escapes;
+        self.ch.nulls = nulls;
+        self
+    }
+
+    /// `with_skip_drain` selects whether to skip discarding any unread data
+    /// from the Bus Pirate after each successful mode entry handshake,
+    /// returning the modified `BusPirate` object.
+    ///
+    /// Discarding that data is the default because it clears out any extra
+    /// responses to the handshake, which would otherwise be mistaken for
+    /// responses to later commands. Skipping it saves polling for data that
+    /// isn't there, which can make a noticeable difference for a program that
+    /// re-enters modes in a tight loop, but is safe only if the Bus Pirate is
+    /// known not to send any such extra data. The setting is retained for
+    /// all of the mode objects derived from this one.
+    pub fn with_skip_drain(mut self, skip: bool) -> Self {
+        self.ch.skip_drain = skip;
+        self
+    }
+
+    /// `set_transition_hook` registers a function to be called each time the
+    /// Bus Pirate successfully changes mode.
+    ///
+    /// The function is given a `ModeTransition` describing the change. It
+    /// remains registered for the lifetime of this `BusPirate` object and all
+    /// of the mode objects derived from it. This can be used, for example, to
+    /// produce a trace of the mode changes in a host program or test.
+    pub fn set_transition_hook(&mut self, hook: fn(ModeTransition)) {
+        self.ch.hook = Some(hook);
+    }
+
     /// `to_bitbang` directs the Bus Pirate to move into "binary bit-bang" mode.
     ///
     /// The Bus Pirate requires several steps to properly switch from terminal
@@ -103,26 +178,223 @@ where
     /// `to_bitbang` consumes the `BusPirate` object and returns a `BitBang`
     /// object in its place. To recover the `BusPirate` object, call `close`
     /// on the `BitBang` object to reset the Bus Pirate back into terminal mode.
-    pub fn to_bitbang(mut self) -> Result<bitbang::BitBang<TX, RX>, Error<TXErr, RXErr>> {
-        // The Bus Pirate could be in any mode when we find it, so
-        // we follow the advice given in the protocol documentation:
-        // - Send newline 10 times to escape from any menu/prompts in progress
-        // - Send '#' to reset
-        // - Send nul (0x00) 20 times to enter binary protocol mode
+    ///
+    /// If the Bus Pirate does not confirm that it has entered bit-bang mode,
+    /// the error is `EntryFailed`, which describes how far the Bus Pirate got
+    /// through the sequence.
+    pub fn to_bitbang(self) -> Result<bitbang::BitBang<TX, RX>, Error<TXErr, RXErr>> {
+        // If the Bus Pirate is behaving as expected then its initialization
+        // messages and "HiZ>" prompt will be discarded here.
+        let (bb, _) = self.enter_bitbang(&mut [])?;
+        Ok(bb)
+    }
 
-        for _ in 0..10 {
-            self.ch.write(0x10)?;
+    /// `to_bitbang_fast` is a variant of `to_bitbang` that first checks
+    /// whether the Bus Pirate is already able to enter bit-bang mode
+    /// immediately, skipping the slower reset sequence if so.
+    ///
+    /// This is the case when the Bus Pirate is already in one of the binary
+    /// modes, such as when a previous program exited without resetting it.
+    /// This is just a best-effort optimization: if the Bus Pirate doesn't
+    /// respond as expected to the first attempt then `to_bitbang_fast` falls
+    /// back to the full sequence used by `to_bitbang`.
+    pub fn to_bitbang_fast(mut self) -> Result<bitbang::BitBang<TX, RX>, Error<TXErr, RXErr>> {
+        self.ch.eat_rx_buffer()?;
+        match version_handshake(&mut self.ch, 0x00, 1, |got| got == &PROTO_VERSION_MSG) {
+            Ok(()) => {
+                self.ch.tx.reset_on_drop = true;
+                self.ch.transition(Mode::Terminal, Mode::BitBang);
+                Ok(bitbang::BitBang::new(self.ch))
+            }
+            Err(Error::Protocol) => self.to_bitbang(),
+            Err(err) => Err(err),
         }
-        self.ch.write('#' as u8)?;
-        self.ch.write(0x10)?;
+    }
+
+    /// `to_bitbang_with_banner` is a variant of `to_bitbang` that captures the
+    /// text the Bus Pirate prints after being reset, rather than discarding it.
+    ///
+    /// That text includes the hardware and firmware versions, which can be
+    /// useful to log or display. Up to `banner.len()` bytes are written into
+    /// the given buffer, and the number of bytes written is returned along
+    /// with the `BitBang` object. Any further text is discarded.
+    ///
+    /// Text that arrives while the null bytes are being sent is also
+    /// included, up until the Bus Pirate confirms entry into bit-bang mode, so
+    /// that a slow reset doesn't cut the banner short.
+    pub fn to_bitbang_with_banner(
+        self,
+        banner: &mut [u8],
+    ) -> Result<(bitbang::BitBang<TX, RX>, usize), Error<TXErr, RXErr>> {
+        self.enter_bitbang(banner)
+    }
+
+    /// `recover` is a more aggressive variant of `to_bitbang` intended for
+    /// a Bus Pirate that has been left partway through a binary mode command,
+    /// such as when a program crashed while communicating with it.
+    ///
+    /// In that situation the Bus Pirate may interpret the bytes `to_bitbang`
+    /// sends as arguments to the incomplete command, and so fail to respond.
+    /// `recover` first sends enough null bytes to complete any binary mode
+    /// command, discarding any responses, and resets the Bus Pirate back to
+    /// terminal mode before then following the same steps as `to_bitbang`.
+    /// This takes considerably longer than `to_bitbang`.
+    pub fn recover(mut self) -> Result<bitbang::BitBang<TX, RX>, Error<TXErr, RXErr>> {
+        // The longest possible command is SPI "write then read" with 4096
+        // bytes to write after its four length bytes. Once the incomplete
+        // command is satisfied, each further null byte returns the Bus Pirate
+        // to bit-bang mode from wherever it is. We don't use any other value
+        // here, because in bit-bang mode most other bytes change pin states.
+        for _ in 0..(4104 / 8) {
+            for _ in 0..8 {
+                self.ch.write(0x00)?;
+            }
+            self.ch.flush()?;
+            // Each null received in bit-bang mode produces a version string,
+            // so we must keep draining them as we go.
+            self.ch.eat_rx_buffer()?;
+        }
+
+        // Now we should be in bit-bang mode, so we can reset from there.
+        self.ch.write(0b00001111)?;
         self.ch.flush()?;
+        self.ch.eat_rx_buffer()?;
+
+        self.to_bitbang()
+    }
 
-        // Before we go any further, we'll read out anything that's in the
-        // receive buffer. If the Bus Pirate is behaving as expected then
-        // its initialization messages and "HiZ>" prompt will be there.
+    /// `ping` checks that the Bus Pirate is connected and responding by
+    /// briefly entering "binary bit-bang" mode and then resetting it back
+    /// into terminal mode.
+    ///
+    /// This is intended as a quick diagnostic before attempting to use the
+    /// Bus Pirate for real. If nothing at all is received in response, which
+    /// suggests a problem with the cable or serial port settings, the result
+    /// is the `Timeout` error. If the Bus Pirate responds but not with the
+    /// expected bit-bang mode version string, which suggests unsupported
+    /// firmware, the result is the `Protocol` error.
+    pub fn ping(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        self.terminal_reset()?;
         self.ch.eat_rx_buffer()?;
 
-        binary_reset_handshake(self.ch)
+        let tries = self.ch.nulls;
+        match version_exchange(&mut self.ch, 0x00, tries, |got| got == &PROTO_VERSION_MSG)? {
+            Exchange::Accepted => (),
+            Exchange::Rejected => return Err(Error::Protocol),
+            Exchange::Silent => return Err(Error::Timeout),
+        }
+
+        // Back to terminal mode, discarding the text printed on reset.
+        self.ch.write(0b00001111)?;
+        self.ch.flush()?;
+        self.ch.eat_rx_buffer()
+    }
+
+    /// `detect_mode` probes the Bus Pirate to find which binary mode it is in,
+    /// without resetting it, such as after the host program restarted while
+    /// the Bus Pirate was still in a binary mode.
+    ///
+    /// The SPI mode version command is sent first, because it leaves an SPI
+    /// mode Bus Pirate and its settings untouched. However, in bit-bang mode
+    /// that same byte enters SPI mode, so a Bus Pirate that was in bit-bang
+    /// mode is reported as being in SPI mode, which it then is. If that gets
+    /// no recognizable response, a null byte is sent, which confirms
+    /// bit-bang mode (entering it first, from any other binary mode). If
+    /// neither is recognized, the result is `DetectedMode::Unknown`, which is
+    /// what a Bus Pirate in terminal mode produces.
+    ///
+    /// The result describes the mode the Bus Pirate is in after probing. A
+    /// Bus Pirate in any binary mode can then be returned to bit-bang mode
+    /// without a full reset, using `to_bitbang_fast`.
+    pub fn detect_mode(&mut self) -> Result<DetectedMode, Error<TXErr, RXErr>> {
+        self.ch.eat_rx_buffer()?;
+
+        #[cfg(feature = "spi")]
+        {
+            let accept = |got: &[u8; 4]| got == &PROTO_SPI_VERSION_MSG;
+            if let Exchange::Accepted = version_exchange(&mut self.ch, 0x01, 1, accept)? {
+                return Ok(DetectedMode::SPI);
+            }
+            self.ch.eat_rx_buffer()?;
+        }
+
+        let accept = |got: &[u8; 5]| got == &PROTO_VERSION_MSG;
+        if let Exchange::Accepted = version_exchange(&mut self.ch, 0x00, 1, accept)? {
+            return Ok(DetectedMode::BitBang);
+        }
+        self.ch.eat_rx_buffer()?;
+        Ok(DetectedMode::Unknown)
+    }
+
+    /// `verify_link` checks that the serial port is configured to match the
+    /// Bus Pirate's framing, of 8 data bits with no parity and one stop bit,
+    /// by briefly entering "binary bit-bang" mode and checking that its
+    /// version string arrives intact several times over, and then resetting
+    /// it back into terminal mode.
+    ///
+    /// The version string contains bytes with both odd and even numbers of
+    /// set bits, so a mismatched parity or character size setting will
+    /// usually corrupt it, resulting in the `LinkMismatch` error. A mismatch
+    /// can also prevent the Bus Pirate from understanding the request at all,
+    /// in which case the result is the `Timeout` error as for `ping`. Calling
+    /// this first gives a clearer diagnosis than a failed mode handshake.
+    pub fn verify_link(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        self.terminal_reset()?;
+        self.ch.eat_rx_buffer()?;
+
+        let accept = |got: &[u8; 5]| got == &PROTO_VERSION_MSG;
+        let tries = self.ch.nulls;
+        match version_exchange(&mut self.ch, 0x00, tries, accept)? {
+            Exchange::Accepted => (),
+            Exchange::Rejected => return Err(Error::LinkMismatch),
+            Exchange::Silent => return Err(Error::Timeout),
+        }
+
+        // Each further null byte gets another copy of the version string.
+        for _ in 0..LINK_CHECKS {
+            match version_exchange(&mut self.ch, 0x00, 1, accept)? {
+                Exchange::Accepted => (),
+                _ => return Err(Error::LinkMismatch),
+            }
+        }
+
+        // Back to terminal mode, discarding the text printed on reset.
+        self.ch.write(0b00001111)?;
+        self.ch.flush()?;
+        self.ch.eat_rx_buffer()
+    }
+
+    /// `enter_bootloader` resets the Bus Pirate and then instructs it to jump
+    /// to its bootloader, ready for new firmware to be uploaded.
+    ///
+    /// This uses the terminal mode's `$` command, answering yes to its
+    /// confirmation prompt, and so requires firmware that offers that command
+    /// along with a bootloader that supports it. The serial transmit and
+    /// receive objects are returned so that they can be passed on to a
+    /// firmware upload implementation.
+    ///
+    /// The confirmation is sent only once the Bus Pirate has printed the
+    /// terminal prompt after the reset and then the `y/n` confirmation
+    /// prompt. If either doesn't arrive, the result is the `Protocol` error
+    /// and the Bus Pirate is left in terminal mode.
+    ///
+    /// Once in the bootloader, the Bus Pirate no longer responds to this
+    /// library at all. It returns to normal operation only once it is
+    /// power-cycled, or when the bootloader is told to run the firmware.
+    pub fn enter_bootloader(mut self) -> Result<(TX, RX), Error<TXErr, RXErr>> {
+        self.terminal_reset()?;
+        wait_for_text(&mut self.ch, b"HiZ>")?;
+        self.ch.eat_rx_buffer()?;
+
+        self.ch.write(b'$')?;
+        self.ch.write(b'\n')?;
+        self.ch.flush()?;
+        wait_for_text(&mut self.ch, b"y/n")?;
+        self.ch.eat_rx_buffer()?;
+        self.ch.write(b'y')?;
+        self.ch.flush()?;
+
+        Ok(self.ch.into_parts())
     }
 
     /// `release` returns the serial transmit and receive objects wrapped by
@@ -130,7 +402,74 @@ where
     ///
     /// This consumes the `BusPirate` object.
     pub fn release(self) -> (TX, RX) {
-        (self.ch.tx, self.ch.rx)
+        self.ch.into_parts()
+    }
+
+    /// `enter_bitbang` implements `to_bitbang` and `to_bitbang_with_banner`,
+    /// capturing as much of the text printed after the reset as will fit in
+    /// `banner`.
+    fn enter_bitbang(
+        mut self,
+        banner: &mut [u8],
+    ) -> Result<(bitbang::BitBang<TX, RX>, usize), Error<TXErr, RXErr>> {
+        let heard_escape = self.terminal_reset()?;
+        let heard_reset = self.ch.read_rx_buffer_into(banner)?;
+
+        // The rest of the banner may still be arriving as we send the null
+        // bytes, so we keep capturing until the version string arrives.
+        let mut len = heard_reset;
+        let record = |c: u8| {
+            if len < banner.len() {
+                banner[len] = c;
+            }
+            len += 1;
+        };
+        let tries = self.ch.nulls;
+        let accept = |got: &[u8; 5]| got == &PROTO_VERSION_MSG;
+        let exchange = version_exchange_recording(&mut self.ch, 0x00, tries, accept, record)?;
+        let stage = match exchange {
+            Exchange::Accepted => {
+                // Only entry from terminal mode arms the reset, so that
+                // forget_reset carries through later mode transitions.
+                self.ch.tx.reset_on_drop = true;
+                self.ch.transition(Mode::Terminal, Mode::BitBang);
+                // The version string itself isn't part of the banner.
+                let len = core::cmp::min(len - PROTO_VERSION_MSG.len(), banner.len());
+                return Ok((bitbang::BitBang::new(self.ch), len));
+            }
+            Exchange::Rejected => EntryStage::BinaryEnter,
+            Exchange::Silent if heard_reset > 0 => EntryStage::BinaryEnter,
+            Exchange::Silent if heard_escape => EntryStage::Reset,
+            Exchange::Silent => EntryStage::Escape,
+        };
+        Err(Error::EntryFailed { stage })
+    }
+
+    /// `terminal_reset` escapes from anything in progress in terminal mode and
+    /// then resets the Bus Pirate, returning true if anything was received in
+    /// response to the escapes.
+    fn terminal_reset(&mut self) -> Result<bool, Error<TXErr, RXErr>> {
+        // The Bus Pirate could be in any mode when we find it, so
+        // we follow the advice given in the protocol documentation:
+        // - Send newline 10 times to escape from any menu/prompts in progress
+        // - Send '#' to reset
+        // - Send nul (0x00) 20 times to enter binary protocol mode
+        // (The counts can be adjusted using with_entry_params.)
+        // The caller deals with the output from the reset and then the
+        // final step is handled by enter_bitbang.
+
+        for _ in 0..self.ch.escapes {
+            self.ch.write(b'\n')?;
+        }
+        self.ch.flush()?;
+        // Each escape normally produces a new prompt. We discard those here so
+        // that the caller sees only the output from the reset.
+        let heard = self.ch.read_rx_buffer_into(&mut [])? > 0;
+
+        self.ch.write('#' as u8)?;
+        self.ch.write(b'\n')?;
+        self.ch.flush()?;
+        Ok(heard)
     }
 }
 
@@ -141,10 +480,45 @@ pub enum Error<TXErr, RXErr> {
     /// response from the Bus Pirate in response to a request.
     Protocol,
 
+    /// `UnexpectedByte` indicates that the Bus Pirate responded to a request
+    /// with a different byte than expected, such as when a command that
+    /// should be acknowledged with 0x01 is not.
+    ///
+    /// This is a more specific form of `Protocol` for situations where there
+    /// is a single expected response byte.
+    UnexpectedByte { expected: u8, got: u8 },
+
     /// `Request` indicates that the caller provided invalid arguments that
     /// could not be checked at compile time.
     Request,
 
+    /// `ModeUnavailable` indicates that the Bus Pirate responded to a request
+    /// to enter the given mode, but not with that mode's version string. This
+    /// usually means that its firmware was built without support for the
+    /// mode.
+    ModeUnavailable(Mode),
+
+    /// `LinkMismatch` indicates that the Bus Pirate's responses arrived
+    /// corrupted in a way that suggests the serial port is not configured to
+    /// match it, as detected by `BusPirate::verify_link`. The Bus Pirate
+    /// always uses 8 data bits, no parity and one stop bit.
+    LinkMismatch,
+
+    /// `EntryFailed` indicates that the Bus Pirate did not confirm entry into
+    /// "binary bit-bang" mode from terminal mode. The stage is a best-effort
+    /// hint at how far the Bus Pirate got through the entry sequence, meant
+    /// for diagnostic messages rather than for deciding how to recover.
+    EntryFailed { stage: EntryStage },
+
+    /// `Timeout` indicates that the Bus Pirate did not respond within the
+    /// configured read timeout, such as the one set by `spi::SPI::set_read_timeout`.
+    ///
+    /// `BusPirate::ping` and `BusPirate::verify_link` also return `Timeout`
+    /// when nothing at all is received in response to any of the bytes they
+    /// send, even though no read timeout is configured for them. That
+    /// usually means that the Bus Pirate isn't connected or isn't powered.
+    Timeout,
+
     /// `Write` indicates that the underlying serial write object returned an
     /// error.
     ///
@@ -159,6 +533,28 @@ pub enum Error<TXErr, RXErr> {
 }
 
 impl<TXErr, RXErr> Error<TXErr, RXErr> {
+    /// `is_recoverable` returns true if the error might be resolved by
+    /// retrying, possibly after re-entering the current binary mode.
+    ///
+    /// Protocol errors and timeouts can be caused by transient corruption or
+    /// delays on the serial link, so they are considered recoverable. Errors
+    /// from the underlying serial implementation usually mean that the link
+    /// itself is broken, and invalid requests would fail again in the same
+    /// way, so those are not.
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            Error::Protocol => true,
+            Error::UnexpectedByte { .. } => true,
+            Error::Request => false,
+            Error::ModeUnavailable(_) => false,
+            Error::LinkMismatch => false,
+            Error::EntryFailed { .. } => true,
+            Error::Timeout => true,
+            Error::Write(_) => false,
+            Error::Read(_) => false,
+        }
+    }
+
     fn tx(got: TXErr) -> Self {
         Error::Write(got)
     }
@@ -168,69 +564,372 @@ impl<TXErr, RXErr> Error<TXErr, RXErr> {
     }
 }
 
-fn binary_mode_handshake<TX: serial::Write<u8>, RX: serial::Read<u8>>(
-    mut ch: low::Channel<TX, RX>,
-    send: u8,
-    expect: &'static [u8; 4],
-) -> Result<low::Channel<TX, RX>, Error<TX::Error, RX::Error>> {
-    let mut ok = false;
-    'tries: for _ in 0..10 {
-        ch.flush()?;
-        ch.write(send)?;
+/// `MapSerialError` adds methods to `Result` for converting errors from the
+/// underlying serial implementation into this crate's `Error`.
+///
+/// This is useful when writing functions that use the serial objects
+/// directly alongside this library, such as after `BusPirate::release`:
+///
+/// ```rust,ignore
+/// nb::block!(tx.write(b'i')).map_tx()?;
+/// let c = nb::block!(rx.read()).map_rx()?;
+/// ```
+pub trait MapSerialError<T, E> {
+    /// `map_tx` wraps an error from a serial write object in `Error::Write`.
+    fn map_tx<RXErr>(self) -> Result<T, Error<E, RXErr>>;
 
-        let mut correct = 0;
-        loop {
-            match ch.rx.read() {
-                Ok(c) => {
-                    if c != expect[correct] {
-                        correct = 0;
-                    }
-                    if c == expect[correct] {
-                        correct += 1;
-                        if correct == expect.len() {
-                            ok = true;
-                            break 'tries;
-                        }
-                    }
-                }
-                Err(e) => match e {
-                    nb::Error::WouldBlock => continue 'tries,
-                    nb::Error::Other(e) => return Err(Error::rx(e)),
-                },
+    /// `map_rx` wraps an error from a serial read object in `Error::Read`.
+    fn map_rx<TXErr>(self) -> Result<T, Error<TXErr, E>>;
+}
+
+impl<T, E> MapSerialError<T, E> for Result<T, E> {
+    fn map_tx<RXErr>(self) -> Result<T, Error<E, RXErr>> {
+        self.map_err(Error::Write)
+    }
+
+    fn map_rx<TXErr>(self) -> Result<T, Error<TXErr, E>> {
+        self.map_err(Error::Read)
+    }
+}
+
+/// `ClockRate` is implemented by the types that select a clock speed for
+/// each of the protocol modes, allowing them to be converted to and from
+/// clock rates in hertz.
+pub trait ClockRate: Sized + Copy + 'static {
+    /// `RATES` lists all of the speeds supported by the mode, from slowest to
+    /// fastest.
+    const RATES: &'static [Self];
+
+    /// `to_hz` returns the clock rate that the speed represents, in hertz.
+    fn to_hz(&self) -> u32;
+
+    /// `from_hz` returns the fastest supported speed whose clock rate does not
+    /// exceed the given rate in hertz, or the slowest supported speed if all
+    /// of them are faster than the given rate.
+    fn from_hz(hz: u32) -> Self {
+        let mut ret = Self::RATES[0];
+        for rate in Self::RATES {
+            if rate.to_hz() <= hz {
+                ret = *rate;
             }
         }
+        ret
     }
 
-    if !ok {
-        return Err(Error::Protocol);
+    /// `closest` returns the supported speed whose clock rate is nearest to
+    /// the given rate in hertz, whether faster or slower.
+    ///
+    /// Use `from_hz` instead when the given rate is a maximum that must not
+    /// be exceeded, such as a device's maximum clock rate.
+    fn closest(hz: u32) -> Self {
+        let mut ret = Self::RATES[0];
+        for rate in Self::RATES {
+            let diff = (rate.to_hz() as i64 - hz as i64).abs();
+            if diff < (ret.to_hz() as i64 - hz as i64).abs() {
+                ret = *rate;
+            }
+        }
+        ret
+    }
+}
+
+/// `Mode` identifies one of the Bus Pirate's modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// `Terminal` is the normal terminal mode, represented by `BusPirate`.
+    Terminal,
+
+    /// `BitBang` is "binary bit-bang" mode, represented by `bitbang::BitBang`.
+    BitBang,
+
+    /// `SPI` is SPI mode, represented by `spi::SPI`.
+    SPI,
+}
+
+/// `EntryStage` identifies a step in the sequence for entering "binary
+/// bit-bang" mode from terminal mode, as reported by `Error::EntryFailed`.
+///
+/// The stage is only a best-effort hint. The Bus Pirate's responses are not
+/// checked until the end of the sequence, so it is a guess based on whether
+/// anything at all was received after each step, and there is no way to
+/// tell which step a response belongs to. A slow serial link may deliver a
+/// response to the escapes after the reset has been sent, which is then
+/// reported as `BinaryEnter` rather than `Reset`. Callers should use it
+/// only to make error messages more helpful.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryStage {
+    /// `Escape` means that nothing was received in response to any part of
+    /// the sequence, starting with the newlines sent to escape from any menu
+    /// in progress. The Bus Pirate may not be connected, or the serial port
+    /// settings may be wrong.
+    Escape,
+
+    /// `Reset` means that the Bus Pirate responded to the escapes, but not to
+    /// the reset command or anything after it.
+    Reset,
+
+    /// `BinaryEnter` means that the Bus Pirate responded to the reset, but
+    /// not with the bit-bang mode version string when sent null bytes. The
+    /// firmware may not support the binary protocol.
+    BinaryEnter,
+}
+
+/// `DetectedMode` is the result of `BusPirate::detect_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectedMode {
+    /// `BitBang` means that the Bus Pirate is in "binary bit-bang" mode.
+    BitBang,
+
+    /// `SPI` means that the Bus Pirate is in SPI mode.
+    SPI,
+
+    /// `Unknown` means that the Bus Pirate did not respond as expected in
+    /// any of the binary modes. It may be in terminal mode, or not connected.
+    Unknown,
+}
+
+/// `ModeMarker` is implemented by the types representing the protocol modes
+/// that can be entered from bit-bang mode, such as `spi::SPI`.
+///
+/// It describes how to enter each mode, which allows `BitBang::enter` to
+/// switch into any of them, and allows writing code that is generic over the
+/// target mode:
+///
+/// ```rust,ignore
+/// fn open<M: ModeMarker<TX, RX>>(bb: BitBang<TX, RX>) -> Result<M, Error<TXErr, RXErr>> {
+///     Ok(bb.enter::<M>()?)
+/// }
+/// ```
+///
+/// This trait is sealed, so it cannot be implemented outside of this crate.
+pub trait ModeMarker<TX: serial::Write<u8>, RX: serial::Read<u8>>: sealed::Sealed<TX, RX> {
+    /// `MODE` identifies the mode in errors and transition hooks.
+    const MODE: Mode;
+
+    /// `VERSION_MSG` is the version string the Bus Pirate responds with on
+    /// entering the mode.
+    const VERSION_MSG: [u8; 4];
+
+    /// `ENTER_COMMAND` is the bit-bang mode command byte that enters the mode.
+    const ENTER_COMMAND: u8;
+}
+
+// Channel is crate-private, but that's fine here because nothing outside the
+// crate can name `Sealed`.
+#[allow(private_interfaces)]
+mod sealed {
+    use crate::low;
+    use embedded_hal::serial;
+
+    /// `Sealed` prevents implementing `ModeMarker` outside of this crate, and
+    /// also holds its methods that use crate-private types.
+    pub trait Sealed<TX: serial::Write<u8>, RX: serial::Read<u8>> {
+        /// `from_channel` wraps a channel that has just entered the mode.
+        fn from_channel(ch: low::Channel<TX, RX>) -> Self;
+    }
+}
+
+/// `ModeTransition` describes a change between two of the Bus Pirate's modes,
+/// as reported to a hook registered with `BusPirate::set_transition_hook`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeTransition {
+    pub from: Mode,
+    pub to: Mode,
+}
+
+/// `TransitionError` is the error type for transitions between binary modes.
+///
+/// A failed transition does not consume the object representing the mode the
+/// Bus Pirate was in beforehand, so the caller can recover it from `previous`
+/// and retry without needing to re-open the serial channels.
+///
+/// `TransitionError` converts into a plain `Error` (discarding `previous`),
+/// so the `?` operator can still be used with transitions in functions that
+/// return `Error`.
+pub struct TransitionError<M, TXErr, RXErr> {
+    /// `previous` is the object for the mode the transition started from.
+    pub previous: M,
+
+    /// `error` describes why the transition failed.
+    pub error: Error<TXErr, RXErr>,
+}
+
+/// `CloseError` is the error type for the `close_checked` methods of the
+/// binary modes, which return the `BusPirate` object on failure.
+pub type CloseError<TX, RX, TXErr, RXErr> = TransitionError<BusPirate<TX, RX>, TXErr, RXErr>;
+
+impl<M, TXErr, RXErr> From<TransitionError<M, TXErr, RXErr>> for Error<TXErr, RXErr> {
+    fn from(err: TransitionError<M, TXErr, RXErr>) -> Self {
+        err.error
     }
+}
 
-    ch.eat_rx_buffer()?;
+impl<M, TXErr, RXErr> core::fmt::Debug for TransitionError<M, TXErr, RXErr>
+where
+    TXErr: core::fmt::Debug,
+    RXErr: core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("TransitionError")
+            .field("error", &self.error)
+            .finish()
+    }
+}
 
-    Ok(ch)
+fn binary_mode_handshake<TX, RX, F>(
+    ch: &mut low::Channel<TX, RX>,
+    send: u8,
+    mode: Mode,
+    accept: F,
+) -> Result<(), Error<TX::Error, RX::Error>>
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+    F: Fn(&[u8; 4]) -> bool,
+{
+    match version_exchange(ch, send, 10, accept)? {
+        Exchange::Accepted => Ok(()),
+        // The Bus Pirate is responding, but not with the version string for
+        // the requested mode, so the firmware probably doesn't support it.
+        Exchange::Rejected => Err(Error::ModeUnavailable(mode)),
+        Exchange::Silent => Err(Error::Protocol),
+    }
 }
 
 fn binary_reset_handshake<TX: serial::Write<u8>, RX: serial::Read<u8>>(
-    mut ch: low::Channel<TX, RX>,
-) -> Result<bitbang::BitBang<TX, RX>, Error<TX::Error, RX::Error>> {
+    ch: &mut low::Channel<TX, RX>,
+    from: Mode,
+) -> Result<(), Error<TX::Error, RX::Error>> {
+    let tries = ch.nulls;
+    version_handshake(ch, 0x00, tries, |got| got == &PROTO_VERSION_MSG)?;
+    ch.transition(from, Mode::BitBang);
+    Ok(())
+}
+
+/// `version_handshake` repeatedly sends the given command byte until the
+/// most recent `N` bytes received from the Bus Pirate are accepted by the
+/// given function, and then discards any further buffered data.
+fn version_handshake<TX, RX, F, const N: usize>(
+    ch: &mut low::Channel<TX, RX>,
+    send: u8,
+    tries: u8,
+    accept: F,
+) -> Result<(), Error<TX::Error, RX::Error>>
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+    F: Fn(&[u8; N]) -> bool,
+{
+    match version_exchange(ch, send, tries, accept)? {
+        Exchange::Accepted => Ok(()),
+        _ => Err(Error::Protocol),
+    }
+}
+
+/// `PROMPT_MAX_BYTES` is the number of bytes `wait_for_text` reads before
+/// giving up on finding the text it's looking for.
+const PROMPT_MAX_BYTES: usize = 1024;
+/// `PROMPT_POLLS` is the number of times `wait_for_text` polls for each byte
+/// before giving up on the Bus Pirate saying anything more.
+const PROMPT_POLLS: u32 = 100_000;
+
+/// `wait_for_text` reads from the Bus Pirate until the given text has been
+/// received, returning the `Protocol` error if it doesn't arrive within
+/// `PROMPT_MAX_BYTES` bytes or the Bus Pirate stops responding first.
+fn wait_for_text<TX, RX>(
+    ch: &mut low::Channel<TX, RX>,
+    text: &[u8],
+) -> Result<(), Error<TX::Error, RX::Error>>
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+{
+    let mut matched = 0;
+    for _ in 0..PROMPT_MAX_BYTES {
+        let c = match ch.read_timeout(PROMPT_POLLS) {
+            Ok(c) => c,
+            Err(Error::Timeout) => return Err(Error::Protocol),
+            Err(err) => return Err(err),
+        };
+        // None of the texts we wait for repeat their first byte, so a
+        // mismatch can only restart the match at the current byte.
+        matched = if c == text[matched] {
+            matched + 1
+        } else if c == text[0] {
+            1
+        } else {
+            0
+        };
+        if matched == text.len() {
+            return Ok(());
+        }
+    }
+    Err(Error::Protocol)
+}
+
+/// `Exchange` is the outcome of `version_exchange`.
+enum Exchange {
+    /// `Accepted` means that an acceptable version string was received.
+    Accepted,
+    /// `Rejected` means that some data was received, but never an acceptable
+    /// version string.
+    Rejected,
+    /// `Silent` means that nothing at all was received.
+    Silent,
+}
+
+/// `version_exchange` is the implementation of `version_handshake`, which
+/// also distinguishes between receiving the wrong response and receiving no
+/// response at all.
+fn version_exchange<TX, RX, F, const N: usize>(
+    ch: &mut low::Channel<TX, RX>,
+    send: u8,
+    tries: u8,
+    accept: F,
+) -> Result<Exchange, Error<TX::Error, RX::Error>>
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+    F: Fn(&[u8; N]) -> bool,
+{
+    version_exchange_recording(ch, send, tries, accept, |_| ())
+}
+
+/// `version_exchange_recording` is a variant of `version_exchange` that also
+/// passes each byte received before the version string is accepted, along
+/// with the version string itself, to the given `record` function.
+fn version_exchange_recording<TX, RX, F, R, const N: usize>(
+    ch: &mut low::Channel<TX, RX>,
+    send: u8,
+    tries: u8,
+    accept: F,
+    mut record: R,
+) -> Result<Exchange, Error<TX::Error, RX::Error>>
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+    F: Fn(&[u8; N]) -> bool,
+    R: FnMut(u8),
+{
+    let mut heard = false;
     let mut ok = false;
-    'tries: for _ in 0..20 {
+    'tries: for _ in 0..tries {
+        ch.write(send)?;
         ch.flush()?;
-        ch.write(0x00)?;
 
-        let mut correct = 0;
+        let mut window = [0_u8; N];
+        let mut seen = 0;
         loop {
-            match ch.rx.read() {
+            match ch.poll() {
                 Ok(c) => {
-                    if c != PROTO_VERSION_MSG[correct] {
-                        correct = 0;
-                    }
-                    if c == PROTO_VERSION_MSG[correct] {
-                        correct += 1;
-                        if correct == PROTO_VERSION_MSG.len() {
-                            ok = true;
-                            break 'tries;
-                        }
+                    heard = true;
+                    record(c);
+                    window.rotate_left(1);
+                    window[N - 1] = c;
+                    seen += 1;
+                    if seen >= N && accept(&window) {
+                        ok = true;
+                        break 'tries;
                     }
                 }
                 Err(e) => match e {
@@ -242,17 +941,59 @@ fn binary_reset_handshake<TX: serial::Write<u8>, RX: serial::Read<u8>>(
     }
 
     if !ok {
-        return Err(Error::Protocol);
+        return Ok(if heard {
+            Exchange::Rejected
+        } else {
+            Exchange::Silent
+        });
     }
 
-    ch.eat_rx_buffer()?;
+    if !ch.skip_drain {
+        ch.eat_rx_buffer()?;
+    }
+    Ok(Exchange::Accepted)
+}
 
-    Ok(bitbang::BitBang { ch: ch })
+/// `close_checked_handshake` implements `close_checked` for all of the binary
+/// modes.
+fn close_checked_handshake<TX, RX, TXErr, RXErr, D>(
+    mut ch: low::Channel<TX, RX>,
+    from: Mode,
+    delay: &mut D,
+    ms: u16,
+) -> Result<BusPirate<TX, RX>, CloseError<TX, RX, TXErr, RXErr>>
+where
+    TX: serial::Write<u8, Error = TXErr>,
+    RX: serial::Read<u8, Error = RXErr>,
+    D: DelayMs<u16>,
+{
+    let result = ch.write(0b00001111).and_then(|_| ch.flush());
+    if let Err(error) = result {
+        return Err(TransitionError {
+            previous: BusPirate { ch },
+            error,
+        });
+    }
+    ch.tx.reset_on_drop = false;
+    ch.transition(from, Mode::Terminal);
+    delay.delay_ms(ms);
+
+    let mut bp = BusPirate { ch };
+    match bp.ping() {
+        Ok(()) => Ok(bp),
+        Err(error) => Err(TransitionError {
+            previous: bp,
+            error,
+        }),
+    }
 }
 
 fn close_handshake<TX: serial::Write<u8>, RX: serial::Read<u8>>(
     mut ch: low::Channel<TX, RX>,
+    from: Mode,
 ) -> Result<BusPirate<TX, RX>, Error<TX::Error, RX::Error>> {
     ch.write(0b00001111)?;
+    ch.tx.reset_on_drop = false;
+    ch.transition(from, Mode::Terminal);
     Ok(BusPirate { ch: ch })
 }
@@ -1,10 +1,34 @@
 use crate::Error;
+use crate::{Mode, ModeTransition};
 use embedded_hal::serial;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub(crate) struct Channel<TX: serial::Write<u8>, RX: serial::Read<u8>> {
-    pub tx: TX,
+    pub tx: Tx<TX>,
     pub rx: RX,
+
+    /// `read_polls` is the maximum number of times `read_data` will poll the
+    /// receive channel before giving up, or `None` to wait indefinitely.
+    pub read_polls: Option<u32>,
+
+    /// `escapes` is the number of newlines sent to escape from any menu
+    /// in progress before resetting the Bus Pirate from terminal mode.
+    pub escapes: u8,
+
+    /// `nulls` is the maximum number of null bytes sent while waiting for
+    /// the Bus Pirate to enter "binary bit-bang" mode.
+    pub nulls: u8,
+
+    /// `skip_drain` is true if any data remaining in the receive buffer after
+    /// a successful mode entry handshake should be left unread.
+    pub skip_drain: bool,
+
+    /// `hook` is called whenever the Bus Pirate changes mode.
+    pub hook: Option<fn(ModeTransition)>,
+
+    /// `pending` holds a byte that was received by `peek` but not yet
+    /// consumed by any of the read methods.
+    pub pending: Option<u8>,
 }
 
 impl<TX, RX, TXErr, RXErr> Channel<TX, RX>
@@ -13,34 +37,144 @@ where
     RX: serial::Read<u8, Error = RXErr>,
 {
     pub fn new(tx: TX, rx: RX) -> Self {
-        Self { tx: tx, rx: rx }
+        Self {
+            tx: Tx {
+                port: Some(tx),
+                reset_on_drop: false,
+            },
+            rx: rx,
+            read_polls: None,
+            escapes: 10,
+            nulls: 20,
+            skip_drain: false,
+            hook: None,
+            pending: None,
+        }
+    }
+
+    /// `into_parts` returns the serial objects without resetting the Bus
+    /// Pirate, regardless of `reset_on_drop`.
+    pub fn into_parts(self) -> (TX, RX) {
+        (self.tx.into_inner(), self.rx)
+    }
+
+    pub fn transition(&self, from: Mode, to: Mode) {
+        if let Some(hook) = self.hook {
+            hook(ModeTransition { from, to });
+        }
+    }
+
+    /// `poll` is like `rx.read`, except that it returns the byte saved by
+    /// `peek` first, if any. All reads should go through here.
+    pub fn poll(&mut self) -> nb::Result<u8, RXErr> {
+        match self.pending.take() {
+            Some(c) => Ok(c),
+            None => self.rx.read(),
+        }
+    }
+
+    /// `peek` returns the next byte received without consuming it, so that
+    /// the next read returns the same byte again. It returns `None` if no
+    /// byte is available yet, without waiting.
+    pub fn peek(&mut self) -> Result<Option<u8>, Error<TXErr, RXErr>> {
+        if self.pending.is_none() {
+            match self.rx.read() {
+                Ok(c) => self.pending = Some(c),
+                Err(nb::Error::WouldBlock) => (),
+                Err(nb::Error::Other(err)) => return Err(Error::rx(err)),
+            }
+        }
+        Ok(self.pending)
+    }
+
+    pub fn has_pending(&mut self) -> Result<bool, Error<TXErr, RXErr>> {
+        Ok(self.peek()?.is_some())
     }
 
     pub fn read(&mut self) -> Result<u8, Error<TXErr, RXErr>> {
-        nb::block!(self.rx.read()).map_err(Error::rx)
+        nb::block!(self.poll()).map_err(Error::rx)
+    }
+
+    pub fn read_timeout(&mut self, max_polls: u32) -> Result<u8, Error<TXErr, RXErr>> {
+        for _ in 0..max_polls {
+            match self.poll() {
+                Ok(c) => return Ok(c),
+                Err(nb::Error::WouldBlock) => continue,
+                Err(nb::Error::Other(err)) => return Err(Error::rx(err)),
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    pub fn read_data(&mut self) -> Result<u8, Error<TXErr, RXErr>> {
+        match self.read_polls {
+            Some(max_polls) => self.read_timeout(max_polls),
+            None => self.read(),
+        }
     }
 
     pub fn write(&mut self, c: u8) -> Result<(), Error<TXErr, RXErr>> {
-        nb::block!(self.tx.write(c)).map_err(Error::tx)
+        nb::block!(self.tx.port().write(c)).map_err(Error::tx)
     }
 
     pub fn flush(&mut self) -> Result<(), Error<TXErr, RXErr>> {
-        nb::block!(self.tx.flush()).map_err(Error::tx)
+        nb::block!(self.tx.port().flush()).map_err(Error::tx)
+    }
+
+    /// `flush_command` is like `flush`, for use after writing a command that
+    /// the Bus Pirate will respond to.
+    ///
+    /// If flushing fails then the command may have been sent anyway, so this
+    /// discards any response that has already arrived before returning the
+    /// error, to give the next command a better chance of reading its own
+    /// response. This is only a best effort, since more of the response may
+    /// arrive later.
+    pub fn flush_command(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        let result = self.flush();
+        if result.is_err() {
+            let _ = self.eat_rx_buffer();
+        }
+        result
     }
 
     pub fn simple_command(&mut self, cmd: u8) -> Result<(), Error<TXErr, RXErr>> {
-        nb::block!(self.tx.write(cmd)).map_err(Error::tx)?;
-        nb::block!(self.tx.flush()).map_err(Error::tx)?;
+        self.write(cmd)?;
+        self.flush_command()?;
 
-        match nb::block!(self.rx.read()).map_err(Error::rx)? {
+        match self.read()? {
             0x01 => Ok(()),
-            _ => Err(Error::Protocol),
+            got => Err(Error::UnexpectedByte {
+                expected: 0x01,
+                got,
+            }),
+        }
+    }
+
+    /// `read_rx_buffer_into` reads everything already received into the given
+    /// buffer, discarding whatever doesn't fit, and returns the total number
+    /// of bytes read. That count may be larger than the buffer.
+    pub fn read_rx_buffer_into(&mut self, buf: &mut [u8]) -> Result<usize, Error<TXErr, RXErr>> {
+        let mut len = 0;
+        loop {
+            match self.poll() {
+                Ok(c) => {
+                    // Keep as much as will fit, and discard the rest.
+                    if len < buf.len() {
+                        buf[len] = c;
+                    }
+                    len += 1;
+                }
+                Err(err) => match err {
+                    nb::Error::WouldBlock => return Ok(len), // Stop if there's nothing else to read
+                    nb::Error::Other(err) => return Err(Error::rx(err)), // Propagate
+                },
+            }
         }
     }
 
     pub fn eat_rx_buffer(&mut self) -> Result<(), Error<TXErr, RXErr>> {
         loop {
-            match self.rx.read() {
+            match self.poll() {
                 Ok(_) => (), // Ignore
                 Err(err) => match err {
                     nb::Error::WouldBlock => return Ok(()), // Stop if there's nothing else to read
@@ -50,3 +184,46 @@ where
         }
     }
 }
+
+/// `Tx` holds the serial write object for a `Channel`, and resets the Bus
+/// Pirate back to terminal mode when dropped if `reset_on_drop` is set.
+#[derive(Debug)]
+pub(crate) struct Tx<TX: serial::Write<u8>> {
+    /// `port` is the serial write object. It is `None` only once
+    /// `into_inner` has taken it, which consumes the `Tx`.
+    port: Option<TX>,
+
+    /// `reset_on_drop` is true if the Bus Pirate should be reset back to
+    /// terminal mode when the channel is dropped.
+    pub reset_on_drop: bool,
+}
+
+impl<TX: serial::Write<u8>> Tx<TX> {
+    pub fn port(&mut self) -> &mut TX {
+        self.port
+            .as_mut()
+            .expect("serial port used after into_inner")
+    }
+
+    /// `into_inner` returns the serial write object, disarming the reset.
+    pub fn into_inner(mut self) -> TX {
+        self.port.take().expect("serial port used after into_inner")
+    }
+}
+
+impl<TX: serial::Write<u8>> Drop for Tx<TX> {
+    fn drop(&mut self) {
+        let port = match self.port.as_mut() {
+            Some(port) if self.reset_on_drop => port,
+            _ => return,
+        };
+
+        // We send 0x00 first to return to bit-bang mode from any other binary
+        // mode, which also interrupts any streaming operation, and then reset
+        // from there. There's nowhere to report errors here, so this is just
+        // a best effort.
+        let _ = nb::block!(port.write(0x00));
+        let _ = nb::block!(port.write(0b00001111));
+        let _ = nb::block!(port.flush());
+    }
+}
@@ -1,7 +1,12 @@
 //! Module `peripherals` represents the general (mode-agnostic) peripherals on
-//! a Bus Pirate module.
+//! a Bus Pirate module, along with pin settings that are shared between the
+//! configurations of the different protocol modes.
 
 /// `Config` represents the configuration of mode-agnostic peripherals.
+///
+/// The default `Config` has all of the peripherals switched off, which is
+/// their state whenever the Bus Pirate enters a new protocol mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Config {
     pub power_supply: bool,
     pub pull_ups: bool,
@@ -10,12 +15,47 @@ pub struct Config {
 }
 
 impl Config {
-    pub(crate) fn command_byte(&self) -> u8 {
-        let mut cmd = 0b10000000 as u8;
-        cmd = cmd | (if self.power_supply { 0 } else { 1 } << 3);
-        cmd = cmd | (if self.pull_ups { 0 } else { 1 } << 2);
-        cmd = cmd | (if self.aux { 0 } else { 1 } << 1);
-        cmd = cmd | (if self.cs { 0 } else { 1 } << 0);
-        cmd
+    /// `command_byte` returns the command that applies this configuration in
+    /// any of the protocol modes, as sent by `spi::SPI::configure_peripherals`.
+    pub fn command_byte(&self) -> u8 {
+        0b01000000
+            | (self.power_supply as u8) << 3
+            | (self.pull_ups as u8) << 2
+            | (self.aux as u8) << 1
+            | (self.cs as u8)
+    }
+}
+
+/// `PinOutput` describes the electrical behavior of the Bus Pirate's output
+/// pins, which is selected separately by each protocol mode's configuration.
+///
+/// In `PinOutputHiZ` the pins behave as open-drain outputs: they actively
+/// pull the line low but float (high impedance) rather than driving it high,
+/// so the line needs a pull-up resistor. This is the setting to use with
+/// devices running at voltages other than 3.3V, up to 5V, by enabling the
+/// on-board pull-ups or by providing external ones.
+///
+/// In `PinOutput3_3V` the pins are push-pull outputs that actively drive the
+/// line high to 3.3V. Such outputs must not be connected to a line that is
+/// pulled up to a different voltage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinOutput {
+    /// `PinOutputHiZ` requests that the Bus Pirate set its outputs to a high
+    /// impedance state when signalling "active".
+    PinOutputHiZ,
+    /// `PinOutput3_3V` requests that the Bus Pirate drive its outputs to 3.3V
+    /// when signalling "active".
+    PinOutput3_3V,
+}
+
+impl PinOutput {
+    /// `config_bit` returns the value of the output-type bit as used in the
+    /// configuration commands of all of the protocol modes.
+    #[cfg_attr(not(feature = "spi"), allow(dead_code))]
+    pub(crate) fn config_bit(&self) -> u8 {
+        match self {
+            PinOutput::PinOutputHiZ => 0,
+            PinOutput::PinOutput3_3V => 1,
+        }
     }
 }
@@ -0,0 +1,17 @@
+//! Module `prelude` re-exports the most commonly used items from this crate,
+//! so that they can all be imported at once:
+//!
+//! ```rust,ignore
+//! use buspirate::prelude::*;
+//! ```
+//!
+//! The configuration types of the different modules share the name `Config`,
+//! so they are re-exported here under more specific names.
+
+pub use crate::bitbang::BitBang;
+#[cfg(feature = "bitbang-gpio")]
+pub use crate::bitbang::{PinId, PinLevels};
+pub use crate::peripherals::Config as PeripheralsConfig;
+#[cfg(feature = "spi")]
+pub use crate::spi::{ChipSelect, Comms, Config as SpiConfig, Speed, SPI};
+pub use crate::{BusPirate, ClockRate, Error, MapSerialError, ModeMarker};
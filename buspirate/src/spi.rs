@@ -13,29 +13,147 @@
 //! The result of `to_spi` is an instance of `SPI`.
 
 use crate::low;
+pub use crate::peripherals::PinOutput;
 use crate::BusPirate;
+use crate::ClockRate;
+use crate::CloseError;
 use crate::Error;
+use crate::TransitionError;
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
 use embedded_hal::serial;
 
+/// `WRITE_THEN_READ_MAX` is the maximum number of bytes that can be written,
+/// and separately the maximum number that can be read, by a single "write
+/// then read" command.
+///
+/// The command's length fields are 16 bits wide, but the v3.6 firmware
+/// buffers the whole transfer in a 4096 byte buffer and rejects any longer
+/// lengths. This library checks the lengths before sending the command, and
+/// the methods that accept longer data split it into multiple commands.
+pub const WRITE_THEN_READ_MAX: usize = 4096;
+
 /// `SPI` represents a Bus Pirate device in SPI mode.
+///
+/// If an `SPI` object is dropped without calling `close`, the Bus Pirate is
+/// reset back into terminal mode on a best-effort basis. Call `forget_reset`
+/// to prevent that.
 pub struct SPI<TX: serial::Write<u8>, RX: serial::Read<u8>> {
     pub(crate) ch: low::Channel<TX, RX>,
+    speed: Option<Speed>,
+    config: Option<Config>,
+    peripherals: crate::peripherals::Config,
+    cs_active: bool,
+    strict_cs: bool,
+    verify: bool,
 }
 
+/// `ModeObject` is the object for either of the modes that
+/// `SPI::measure_voltage` passes through, returned when it fails so that
+/// the caller has an object matching the mode the Bus Pirate is in.
+pub enum ModeObject<TX: serial::Write<u8>, RX: serial::Read<u8>> {
+    /// `BitBang` means the Bus Pirate was left in "binary bit-bang" mode.
+    BitBang(crate::bitbang::BitBang<TX, RX>),
+
+    /// `SPI` means the Bus Pirate was left in SPI mode.
+    SPI(SPI<TX, RX>),
+}
+
+/// `MeasureVoltageError` is the error type for `SPI::measure_voltage`.
+pub type MeasureVoltageError<TX, RX, TXErr, RXErr> =
+    TransitionError<ModeObject<TX, RX>, TXErr, RXErr>;
+
 impl<TX, RX, TXErr, RXErr> SPI<TX, RX>
 where
     TX: serial::Write<u8, Error = TXErr>,
     RX: serial::Read<u8, Error = RXErr>,
 {
+    pub(crate) fn new(ch: low::Channel<TX, RX>) -> Self {
+        Self {
+            ch,
+            speed: None,
+            config: None,
+            peripherals: crate::peripherals::Config::default(),
+            // Chip select is inactive on entry to SPI mode.
+            cs_active: false,
+            strict_cs: false,
+            verify: false,
+        }
+    }
+
     /// `close` resets the Bus Pirate back into normal terminal mode, exiting
     /// SPI mode.
     pub fn close(self) -> Result<BusPirate<TX, RX>, Error<TXErr, RXErr>> {
-        crate::close_handshake(self.ch)
+        crate::close_handshake(self.ch, crate::Mode::SPI)
+    }
+
+    /// `close_checked` is a variant of `close` that confirms that the Bus
+    /// Pirate has actually returned to terminal mode.
+    ///
+    /// After sending the reset command, this waits for the given number of
+    /// milliseconds, using the given delay implementation, to allow the Bus
+    /// Pirate to restart. It then checks that the Bus Pirate is responding,
+    /// in the same way as `BusPirate::ping`. This is much slower than
+    /// `close`, but is useful for a program that must leave the Bus Pirate in
+    /// a known state.
+    ///
+    /// If the Bus Pirate doesn't respond, the error from `ping` is returned
+    /// in a `CloseError` whose `previous` is the `BusPirate` object, so
+    /// that the serial channels are not lost. The same applies if sending the
+    /// reset command fails, since the Bus Pirate may have received it anyway.
+    pub fn close_checked<D: DelayMs<u16>>(
+        self,
+        delay: &mut D,
+        ms: u16,
+    ) -> Result<BusPirate<TX, RX>, CloseError<TX, RX, TXErr, RXErr>> {
+        crate::close_checked_handshake(self.ch, crate::Mode::SPI, delay, ms)
     }
 
     /// `to_bitbang` switches back to "binary bit-bang" mode.
     pub fn to_bitbang(self) -> Result<crate::bitbang::BitBang<TX, RX>, Error<TXErr, RXErr>> {
-        crate::binary_reset_handshake(self.ch)
+        let mut ch = self.ch;
+        crate::binary_reset_handshake(&mut ch, crate::Mode::SPI)?;
+        Ok(crate::bitbang::BitBang::new(ch))
+    }
+
+    /// `into_inner` returns the serial transmit and receive objects wrapped by
+    /// this object, without resetting the Bus Pirate.
+    ///
+    /// The Bus Pirate is left in SPI mode, for a caller that intends to
+    /// continue communicating with it directly or pass it to another library.
+    /// Any data already received but not yet read by this object is lost,
+    /// including a byte held back by `has_pending`. To instead return the
+    /// Bus Pirate to terminal mode, use `close`.
+    pub fn into_inner(self) -> (TX, RX) {
+        self.ch.into_parts()
+    }
+
+    /// `forget_reset` prevents the Bus Pirate from being reset when this
+    /// object, or any mode object later derived from it, is dropped.
+    ///
+    /// Use this to intentionally leave the Bus Pirate in SPI mode after the
+    /// program exits.
+    pub fn forget_reset(&mut self) {
+        self.ch.tx.reset_on_drop = false;
+    }
+
+    /// `flush` blocks until all data previously sent to the Bus Pirate has
+    /// been transmitted by the underlying serial implementation.
+    ///
+    /// Methods that wait for a response from the Bus Pirate already flush
+    /// automatically, so this is needed only when pipelining commands.
+    pub fn flush(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        self.ch.flush()
+    }
+
+    /// `has_pending` returns true if the Bus Pirate has sent data that has
+    /// not yet been read, without blocking to wait for any.
+    ///
+    /// If a byte is available it is kept and returned by the next method that
+    /// reads from the Bus Pirate, so checking for pending data never loses
+    /// any. This allows an event loop to poll for data without committing to
+    /// a blocking read.
+    pub fn has_pending(&mut self) -> Result<bool, Error<TXErr, RXErr>> {
+        self.ch.has_pending()
     }
 
     /// `set_speed` changes the SPI clock rate for subsequent transactions.
@@ -51,12 +169,39 @@ where
             Speed4MHz => 0b110,
             Speed8MHz => 0b111,
         } as u8;
-        self.ch.simple_command(0b01000000 | bits)
+        self.ch.simple_command(0b01100000 | bits)?;
+        self.speed = Some(speed);
+        Ok(())
+    }
+
+    /// `effective_hz` returns the SPI clock rate most recently selected with
+    /// `set_speed`, in hertz.
+    ///
+    /// The result is `None` if `set_speed` has not been called since entering
+    /// SPI mode, in which case the Bus Pirate uses its default speed.
+    pub fn effective_hz(&self) -> Option<u32> {
+        self.speed.map(|speed| speed.to_hz())
+    }
+
+    /// `config_snapshot` returns the settings most recently applied to this
+    /// SPI mode object, for display or debugging.
+    ///
+    /// The snapshot reflects only the settings changed through this object
+    /// since entering SPI mode. It does not query the Bus Pirate.
+    pub fn config_snapshot(&self) -> ConfigSnapshot {
+        ConfigSnapshot {
+            speed: self.speed,
+            config: self.config,
+            peripherals: self.peripherals,
+            cs_active: self.cs_active,
+        }
     }
 
     /// `set_config` changes some SPI-mode-specific configuration settings.
     pub fn set_config(&mut self, config: Config) -> Result<(), Error<TXErr, RXErr>> {
-        self.ch.simple_command(config.command_byte())
+        self.ch.simple_command(config.command_byte())?;
+        self.config = Some(config);
+        Ok(())
     }
 
     /// `configure_peripherals` changes some settings related to general
@@ -65,7 +210,32 @@ where
         &mut self,
         config: crate::peripherals::Config,
     ) -> Result<(), Error<TXErr, RXErr>> {
-        self.ch.simple_command(config.command_byte())
+        self.ch.simple_command(config.command_byte())?;
+        self.peripherals = config;
+        Ok(())
+    }
+
+    /// `power_cycle` switches off the Bus Pirate's on-board power supplies,
+    /// waits for the given number of milliseconds using the given delay
+    /// implementation, and then switches them back on.
+    ///
+    /// The other peripheral settings are left as they were most recently set
+    /// by `configure_peripherals`.
+    pub fn power_cycle<D: DelayMs<u16>>(
+        &mut self,
+        delay: &mut D,
+        ms: u16,
+    ) -> Result<(), Error<TXErr, RXErr>> {
+        let config = self.peripherals;
+        self.configure_peripherals(crate::peripherals::Config {
+            power_supply: false,
+            ..config
+        })?;
+        delay.delay_ms(ms);
+        self.configure_peripherals(crate::peripherals::Config {
+            power_supply: true,
+            ..config
+        })
     }
 
     /// `chip_select` sets the state of the chip select signal.
@@ -75,7 +245,159 @@ where
     /// signal to move high.
     pub fn chip_select(&mut self, active: bool) -> Result<(), Error<TXErr, RXErr>> {
         self.ch
-            .simple_command(if active { 0b00000010 } else { 0b00000011 })
+            .simple_command(if active { 0b00000010 } else { 0b00000011 })?;
+        self.cs_active = active;
+        Ok(())
+    }
+
+    /// `pulse_cs` activates the chip select signal, waits for the given number
+    /// of microseconds using the given delay implementation, and then
+    /// deactivates it again.
+    ///
+    /// This suits devices that latch a command on a brief chip select pulse.
+    /// The pulse will be longer than requested by at least one round trip to
+    /// the Bus Pirate, because the delay happens on the host. If activating
+    /// chip select fails then no attempt is made to deactivate it.
+    pub fn pulse_cs<D: DelayUs<u16>>(
+        &mut self,
+        delay: &mut D,
+        us: u16,
+    ) -> Result<(), Error<TXErr, RXErr>> {
+        self.chip_select(true)?;
+        delay.delay_us(us);
+        self.chip_select(false)
+    }
+
+    /// `set_verify` enables or disables checking that the Bus Pirate's
+    /// responses remain in sync after each `write_then_read`.
+    ///
+    /// With verification enabled, each `write_then_read` is followed by a
+    /// request for the SPI mode version string, which has a known response.
+    /// If any bytes of the response to the transfer were lost or duplicated
+    /// in transit, the version string will not arrive exactly as expected
+    /// and so the `Protocol` error is returned. Lost bytes can cause the
+    /// check to wait indefinitely, so verification is most useful alongside
+    /// `set_read_timeout`.
+    ///
+    /// Verification adds a round trip to every `write_then_read`, so it is
+    /// disabled by default. It is intended for unreliable serial links, such
+    /// as long USB cables.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// `set_strict_cs` enables or disables checking that the chip select
+    /// signal is active before each transfer.
+    ///
+    /// Many SPI devices ignore data sent while chip select is inactive, so
+    /// forgetting to call `chip_select` first is an easy mistake. With strict
+    /// checking enabled, the transfer methods return the `Request` error
+    /// instead of sending anything if chip select was not most recently
+    /// activated using `chip_select`. `write_then_read` with `cs` set to
+    /// true is always allowed, because the Bus Pirate activates chip select
+    /// itself in that case.
+    ///
+    /// Checking is disabled by default, to allow for devices that do not use
+    /// chip select at all.
+    pub fn set_strict_cs(&mut self, strict: bool) {
+        self.strict_cs = strict;
+    }
+
+    /// `set_read_timeout` limits how long data transfers will wait for each
+    /// byte of response from the Bus Pirate.
+    ///
+    /// The limit is given as a number of unsuccessful polls of the serial
+    /// receive channel, so its duration in real time depends on the serial
+    /// implementation. If a transfer exceeds the limit, it returns the
+    /// `Timeout` error. Passing `None` restores the default behavior of
+    /// waiting indefinitely.
+    ///
+    /// After a timeout the Bus Pirate may still be partway through the
+    /// command, so the caller should generally re-enter the mode before
+    /// attempting any further transfers.
+    pub fn set_read_timeout(&mut self, max_polls: Option<u32>) {
+        self.ch.read_polls = max_polls;
+    }
+
+    /// `measure_voltage` takes a single sample of the voltage at the Bus
+    /// Pirate's ADC probe pin, returning the raw 10-bit ADC reading along
+    /// with the SPI mode object.
+    ///
+    /// SPI mode has no command for reading the ADC, so this switches to
+    /// bit-bang mode, takes the sample there, and then re-enters SPI mode.
+    /// Afterwards it re-applies the speed, configuration, peripheral and
+    /// chip select settings most recently set through this object. That is
+    /// several round trips to the Bus Pirate, and switching modes resets
+    /// the peripherals, so the power supplies and pull-ups are briefly
+    /// switched off while the sample is taken.
+    ///
+    /// If any step fails, the error is returned in a `TransitionError` along
+    /// with a `ModeObject` for the mode the Bus Pirate was last known to be
+    /// in, so that the serial channels are not lost. If it didn't confirm
+    /// the switch to bit-bang mode, that is this SPI mode object, unchanged.
+    /// If the sample or the switch back to SPI mode failed, it is a bit-bang
+    /// mode object. If restoring the settings failed, it is the new SPI mode
+    /// object, reflecting the settings restored so far.
+    pub fn measure_voltage(
+        mut self,
+    ) -> Result<(u16, Self), MeasureVoltageError<TX, RX, TXErr, RXErr>> {
+        if let Err(error) = crate::binary_reset_handshake(&mut self.ch, crate::Mode::SPI) {
+            return Err(TransitionError {
+                previous: ModeObject::SPI(self),
+                error,
+            });
+        }
+        let saved = self.config_snapshot();
+        let strict_cs = self.strict_cs;
+        let verify = self.verify;
+
+        let mut bb = crate::bitbang::BitBang::new(self.ch);
+        let raw = match bb.measure_voltage() {
+            Ok(raw) => raw,
+            Err(error) => {
+                return Err(TransitionError {
+                    previous: ModeObject::BitBang(bb),
+                    error,
+                })
+            }
+        };
+        let mut spi = match bb.to_spi() {
+            Ok(spi) => spi,
+            Err(err) => {
+                return Err(TransitionError {
+                    previous: ModeObject::BitBang(err.previous),
+                    error: err.error,
+                })
+            }
+        };
+
+        spi.strict_cs = strict_cs;
+        spi.verify = verify;
+        match spi.restore(saved) {
+            Ok(()) => Ok((raw, spi)),
+            Err(error) => Err(TransitionError {
+                previous: ModeObject::SPI(spi),
+                error,
+            }),
+        }
+    }
+
+    /// `restore` re-applies the settings from the given snapshot, for
+    /// `measure_voltage`.
+    fn restore(&mut self, saved: ConfigSnapshot) -> Result<(), Error<TXErr, RXErr>> {
+        if let Some(speed) = saved.speed {
+            self.set_speed(speed)?;
+        }
+        if let Some(config) = saved.config {
+            self.set_config(config)?;
+        }
+        if saved.peripherals != crate::peripherals::Config::default() {
+            self.configure_peripherals(saved.peripherals)?;
+        }
+        if saved.cs_active {
+            self.chip_select(true)?;
+        }
+        Ok(())
     }
 
     /// `transfer_byte` performs a single-byte SPI transfer.
@@ -88,12 +410,16 @@ where
     /// transmitting, but a common convention for recieving data only is to
     /// transmit zero.
     pub fn transfer_byte(&mut self, v: u8) -> Result<u8, Error<TXErr, RXErr>> {
-        self.ch.write(0b00010000)?;
+        self.check_cs()?;
+        self.ch.write(bulk_transfer_command(1))?;
         self.ch.write(v)?;
-        self.ch.flush()?;
-        match self.ch.read()? {
-            0x01 => self.ch.read(),
-            _ => Err(Error::<TXErr, RXErr>::Protocol),
+        self.ch.flush_command()?;
+        match self.ch.read_data()? {
+            0x01 => self.ch.read_data(),
+            got => Err(Error::UnexpectedByte {
+                expected: 0x01,
+                got,
+            }),
         }
     }
 
@@ -114,26 +440,158 @@ where
             return Err(Error::Request); // Too many bytes to send
         }
 
-        let len = v.len() as u8;
-        let cmd = (0b00010000 as u8) | (len - 1);
-        self.ch.write(cmd)?;
-        for i in 0..v.len() {
-            self.ch.write(v[i])?;
+        self.transfer_bulk(v)?;
+        Ok(v)
+    }
+
+    /// `transfer_bytes_retry` is a variant of `transfer_bytes` that retries
+    /// the transfer, up to `attempts` more times, if the Bus Pirate's
+    /// response appears to be corrupted.
+    ///
+    /// Only the `Protocol` and `UnexpectedByte` errors cause a retry, since
+    /// those are typical of corruption on an unreliable serial link. Before
+    /// each retry, any unread data from the Bus Pirate is discarded and the
+    /// original bytes to transmit are restored. If the final attempt fails,
+    /// its error is returned.
+    ///
+    /// The failed transfer may have reached the device even though its
+    /// response was lost, so the device may see the same bytes more than
+    /// once. Use this only for operations that are safe to repeat, such as
+    /// reading a flash chip's ID.
+    pub fn transfer_bytes_retry<'w>(
+        &mut self,
+        v: &'w mut [u8],
+        attempts: u8,
+    ) -> Result<&'w [u8], Error<TXErr, RXErr>> {
+        if v.len() > 16 {
+            return Err(Error::Request); // Too many bytes to send
         }
-        self.ch.flush()?;
+        let mut orig = [0_u8; 16];
+        orig[..v.len()].copy_from_slice(v);
 
-        match self.ch.read()? {
-            0x01 => (),
-            _ => return Err(Error::<TXErr, RXErr>::Protocol),
+        let mut retries = 0;
+        loop {
+            match self.transfer_bytes(v) {
+                Ok(_) => break,
+                Err(Error::Protocol) | Err(Error::UnexpectedByte { .. }) if retries < attempts => {
+                    retries += 1;
+                    self.ch.eat_rx_buffer()?;
+                    v.copy_from_slice(&orig[..v.len()]);
+                }
+                Err(err) => return Err(err),
+            }
         }
+        Ok(v)
+    }
 
-        for i in 0..v.len() {
-            v[i] = self.ch.read()?;
+    /// `transfer_array` performs a multi-byte SPI transfer of a length known
+    /// at compile time, overwriting the given array in-place with the bytes
+    /// received.
+    ///
+    /// This is equivalent to `transfer_bytes` except that the 16 byte limit
+    /// is checked during compilation: using an array longer than 16 bytes
+    /// is a compile error rather than a `Request` error.
+    ///
+    /// ```rust,no_run
+    /// # use buspirate::test_util::FakeSerial;
+    /// # let (tx, rx) = FakeSerial::new().split();
+    /// # let mut spi = buspirate::BusPirate::new(tx, rx).to_bitbang().unwrap().to_spi().unwrap();
+    /// let mut buf = [0_u8; 16];
+    /// spi.transfer_array(&mut buf).unwrap();
+    /// ```
+    ///
+    /// ```rust,compile_fail
+    /// # use buspirate::test_util::FakeSerial;
+    /// # let (tx, rx) = FakeSerial::new().split();
+    /// # let mut spi = buspirate::BusPirate::new(tx, rx).to_bitbang().unwrap().to_spi().unwrap();
+    /// let mut buf = [0_u8; 17];
+    /// spi.transfer_array(&mut buf).unwrap();
+    /// ```
+    pub fn transfer_array<const N: usize>(
+        &mut self,
+        v: &mut [u8; N],
+    ) -> Result<(), Error<TXErr, RXErr>> {
+        let () = TransferLen::<N>::CHECK;
+        if N == 0 {
+            return Ok(()); // Nothing to do, then.
         }
+        self.transfer_bulk(&mut v[..])
+    }
 
+    /// `transfer_with_progress` is a variant of `Comms::transfer` that calls
+    /// the given function after each bulk transfer command completes, passing
+    /// the total number of bytes transferred so far.
+    ///
+    /// Each command transfers up to 16 bytes, so this is useful for showing
+    /// progress through a long transfer. The last call always reports the
+    /// full length of the given slice, unless an error occurs first. An empty
+    /// slice results in no calls at all.
+    pub fn transfer_with_progress<'w, F>(
+        &mut self,
+        v: &'w mut [u8],
+        mut on_chunk: F,
+    ) -> Result<&'w [u8], Error<TXErr, RXErr>>
+    where
+        F: FnMut(usize),
+    {
+        let mut done = 0;
+        for next in v.chunks_mut(16) {
+            self.transfer_bulk(next)?;
+            done += next.len();
+            on_chunk(done);
+        }
         Ok(v)
     }
 
+    /// `transfer_split` performs a multi-byte SPI transfer using separate
+    /// buffers for the transmitted and received data.
+    ///
+    /// This is equivalent to `transfer_bytes` except that the bytes to transmit
+    /// are left intact, with the received bytes written into `read_into`
+    /// instead. The two slices must have the same length, or the `Request`
+    /// error is returned.
+    ///
+    /// A maximum of 16 bytes can be transmitted per call. If longer slices
+    /// are given, the `Request` error is returned.
+    pub fn transfer_split(
+        &mut self,
+        write_from: &[u8],
+        read_into: &mut [u8],
+    ) -> Result<(), Error<TXErr, RXErr>> {
+        if write_from.len() != read_into.len() {
+            return Err(Error::Request); // Must receive one byte per byte sent
+        }
+        if write_from.is_empty() {
+            return Ok(()); // Nothing to do, then.
+        }
+        if write_from.len() > 16 {
+            return Err(Error::Request); // Too many bytes to send
+        }
+        self.check_cs()?;
+
+        self.ch.write(bulk_transfer_command(write_from.len()))?;
+        for c in write_from {
+            self.ch.write(*c)?;
+        }
+        self.ch.flush_command()?;
+
+        match self.ch.read_data()? {
+            0x01 => (),
+            got => {
+                return Err(Error::UnexpectedByte {
+                    expected: 0x01,
+                    got,
+                })
+            }
+        }
+
+        for c in read_into.iter_mut() {
+            *c = self.ch.read_data()?;
+        }
+
+        Ok(())
+    }
+
     /// `write_then_read` transmits zero or more bytes and then receives zero
     /// or more bytes, optionally setting the chip select signal active
     /// throughout.
@@ -147,46 +605,515 @@ where
     ///
     /// A maximum of 4096 bytes can be transmitted and recieved by this function.
     /// If either slice is greater than 4096 characters then the `Request`
-    /// error is returned.
+    /// error is returned. See `WRITE_THEN_READ_MAX` for the reason for this
+    /// limit, and `write_then_read_chunked` for reading more data.
     pub fn write_then_read<'w>(
         &mut self,
         write_from: &[u8],
         read_into: &mut [u8],
         cs: bool,
     ) -> Result<(), Error<TXErr, RXErr>> {
-        if write_from.len() > 4096 {
+        self.start_write_then_read(
+            write_from.iter().copied(),
+            write_from.len(),
+            read_into.len(),
+            cs,
+        )?;
+
+        for i in 0..read_into.len() {
+            read_into[i] = self.ch.read_data()?;
+        }
+
+        if self.verify {
+            self.verify_sync()?;
+        }
+        Ok(())
+    }
+
+    /// `read_reg_u8` reads a single-byte register from a device that accepts
+    /// a one-byte register address followed by the register's value, as many
+    /// sensors do.
+    ///
+    /// This uses `write_then_read` with chip select active throughout. Any
+    /// read flag a device requires in the address byte must be included in
+    /// `reg` by the caller.
+    pub fn read_reg_u8(&mut self, reg: u8) -> Result<u8, Error<TXErr, RXErr>> {
+        let mut buf = [0; 1];
+        self.write_then_read(&[reg], &mut buf, true)?;
+        Ok(buf[0])
+    }
+
+    /// `read_reg_u16_be` is like `read_reg_u8`, but reads a two-byte register
+    /// whose most significant byte is sent first.
+    pub fn read_reg_u16_be(&mut self, reg: u8) -> Result<u16, Error<TXErr, RXErr>> {
+        let mut buf = [0; 2];
+        self.write_then_read(&[reg], &mut buf, true)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    /// `read_reg_u16_le` is like `read_reg_u8`, but reads a two-byte register
+    /// whose least significant byte is sent first.
+    pub fn read_reg_u16_le(&mut self, reg: u8) -> Result<u16, Error<TXErr, RXErr>> {
+        let mut buf = [0; 2];
+        self.write_then_read(&[reg], &mut buf, true)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    /// `write_reg_u8` writes a single-byte register, sending the one-byte
+    /// register address followed by the value with chip select active
+    /// throughout.
+    pub fn write_reg_u8(&mut self, reg: u8, value: u8) -> Result<(), Error<TXErr, RXErr>> {
+        self.write_then_read(&[reg, value], &mut [], true)
+    }
+
+    /// `write_reg_u16_be` is like `write_reg_u8`, but writes a two-byte
+    /// register with its most significant byte first.
+    pub fn write_reg_u16_be(&mut self, reg: u8, value: u16) -> Result<(), Error<TXErr, RXErr>> {
+        let [hi, lo] = value.to_be_bytes();
+        self.write_then_read(&[reg, hi, lo], &mut [], true)
+    }
+
+    /// `write_reg_u16_le` is like `write_reg_u8`, but writes a two-byte
+    /// register with its least significant byte first.
+    pub fn write_reg_u16_le(&mut self, reg: u8, value: u16) -> Result<(), Error<TXErr, RXErr>> {
+        let [lo, hi] = value.to_le_bytes();
+        self.write_then_read(&[reg, lo, hi], &mut [], true)
+    }
+
+    /// `write_then_read_partial` is a variant of `write_then_read` that gives
+    /// up waiting for response bytes after the given number of unsuccessful
+    /// polls, returning how many bytes were actually received.
+    ///
+    /// This allows detecting a device that responds with fewer bytes than
+    /// requested, or a link that loses some, rather than waiting forever for
+    /// bytes that will never arrive. The limit applies to each byte, including
+    /// the Bus Pirate's acknowledgement of the command, in the same way as
+    /// for `set_read_timeout`, and replaces any limit set by that method for
+    /// the duration of this call. If no acknowledgement arrives then the
+    /// `Timeout` error is returned.
+    ///
+    /// After a partial read, only the first bytes of `read_into` (up to the
+    /// returned count) are written, and the rest are left unchanged. The
+    /// missing bytes may yet arrive later, or the Bus Pirate may still be
+    /// partway through the command, so the caller should generally re-enter
+    /// the mode before attempting any further transfers. Sync verification
+    /// enabled by `set_verify` is skipped after a partial read, since it
+    /// would certainly fail.
+    pub fn write_then_read_partial(
+        &mut self,
+        write_from: &[u8],
+        read_into: &mut [u8],
+        cs: bool,
+        max_polls: u32,
+    ) -> Result<usize, Error<TXErr, RXErr>> {
+        let read_polls = self.ch.read_polls.replace(max_polls);
+        let result = self.write_then_read_partial_inner(write_from, read_into, cs);
+        self.ch.read_polls = read_polls;
+        let count = result?;
+
+        if self.verify && count == read_into.len() {
+            self.verify_sync()?;
+        }
+        Ok(count)
+    }
+
+    /// `write_then_read_partial_inner` implements `write_then_read_partial`
+    /// while its timeout is in effect.
+    fn write_then_read_partial_inner(
+        &mut self,
+        write_from: &[u8],
+        read_into: &mut [u8],
+        cs: bool,
+    ) -> Result<usize, Error<TXErr, RXErr>> {
+        self.start_write_then_read(
+            write_from.iter().copied(),
+            write_from.len(),
+            read_into.len(),
+            cs,
+        )?;
+
+        for (i, c) in read_into.iter_mut().enumerate() {
+            match self.ch.read_data() {
+                Ok(got) => *c = got,
+                Err(Error::Timeout) => return Ok(i),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(read_into.len())
+    }
+
+    /// `write_then_read_iter` is a variant of `write_then_read` that takes
+    /// the bytes to transmit from an iterator, so that a request can be
+    /// generated as it is sent rather than first assembled in a buffer.
+    ///
+    /// The Bus Pirate must be told the length of the write data before any
+    /// of it is sent, so the caller must give it as `write_len`. Only the
+    /// first `write_len` items from `write_from` are used. If the iterator
+    /// ends early, then zero bytes are sent in place of the missing items so
+    /// that the Bus Pirate stays in sync, and the `Request` error is returned
+    /// once the command completes.
+    ///
+    /// ```rust,ignore
+    /// let data = [0xde, 0xad, 0xbe, 0xef];
+    /// let req = core::iter::once(0x02).chain(data.iter().copied());
+    /// spi.write_then_read_iter(req, 1 + data.len(), &mut [], true)?;
+    /// ```
+    pub fn write_then_read_iter<I>(
+        &mut self,
+        write_from: I,
+        write_len: usize,
+        read_into: &mut [u8],
+        cs: bool,
+    ) -> Result<(), Error<TXErr, RXErr>>
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        let mut given = 0;
+        let write_from = write_from
+            .into_iter()
+            .inspect(|_| given += 1)
+            .chain(core::iter::repeat(0));
+        self.start_write_then_read(write_from, write_len, read_into.len(), cs)?;
+
+        for c in read_into.iter_mut() {
+            *c = self.ch.read_data()?;
+        }
+
+        if given < write_len {
+            return Err(Error::Request); // Iterator ended too soon
+        }
+        Ok(())
+    }
+
+    /// `write_delay_read` is a variant of `write_then_read` that waits for
+    /// the given number of microseconds, using the given delay
+    /// implementation, between transmitting and receiving.
+    ///
+    /// This suits devices that need time to prepare their response, such as
+    /// an ADC performing a conversion. The write and the read are sent to
+    /// the Bus Pirate as separate commands, so unlike `write_then_read` the
+    /// timing is not under the Bus Pirate's control, and the actual gap
+    /// will be longer than requested by at least one round trip to the Bus
+    /// Pirate.
+    ///
+    /// If `cs` is true then this method activates chip select before the
+    /// write and deactivates it after the read, even if the operation fails.
+    /// A maximum of 4096 bytes can be transmitted and received. If either
+    /// slice is longer than that, the `Request` error is returned.
+    pub fn write_delay_read<D: DelayUs<u16>>(
+        &mut self,
+        write_from: &[u8],
+        read_into: &mut [u8],
+        cs: bool,
+        delay: &mut D,
+        us: u16,
+    ) -> Result<(), Error<TXErr, RXErr>> {
+        if write_from.len() > WRITE_THEN_READ_MAX || read_into.len() > WRITE_THEN_READ_MAX {
+            return Err(Error::Request); // Too many bytes to send or read
+        }
+        if !cs {
+            return self.write_delay_read_inner(write_from, read_into, delay, us);
+        }
+
+        self.chip_select(true)?;
+        let result = self.write_delay_read_inner(write_from, read_into, delay, us);
+        let deselect = self.chip_select(false);
+        result?;
+        deselect
+    }
+
+    /// `write_delay_read_inner` implements `write_delay_read` without any
+    /// chip select handling. The caller must check the lengths.
+    fn write_delay_read_inner<D: DelayUs<u16>>(
+        &mut self,
+        write_from: &[u8],
+        read_into: &mut [u8],
+        delay: &mut D,
+        us: u16,
+    ) -> Result<(), Error<TXErr, RXErr>> {
+        self.start_write_then_read(write_from.iter().copied(), write_from.len(), 0, false)?;
+        delay.delay_us(us);
+        self.start_write_then_read(core::iter::empty(), 0, read_into.len(), false)?;
+        for c in read_into.iter_mut() {
+            *c = self.ch.read_data()?;
+        }
+        Ok(())
+    }
+
+    /// `repeat_byte` transmits the given byte `count` times, discarding the
+    /// bytes received.
+    ///
+    /// This is useful for clocking out fill patterns or dummy bytes without
+    /// allocating a buffer. The bytes are sent using `write_then_read`
+    /// commands of up to 4096 bytes each, without changing the chip select
+    /// signal, so the caller should activate chip select first if the
+    /// device requires it.
+    pub fn repeat_byte(&mut self, byte: u8, count: usize) -> Result<(), Error<TXErr, RXErr>> {
+        let mut remain = count;
+        while remain > 0 {
+            let len = if remain > WRITE_THEN_READ_MAX {
+                WRITE_THEN_READ_MAX
+            } else {
+                remain
+            };
+            self.start_write_then_read(core::iter::repeat(byte), len, 0, false)?;
+            remain -= len;
+        }
+        Ok(())
+    }
+
+    /// `write_then_read_chunked` is a variant of `write_then_read` that
+    /// delivers the received data in chunks, so that more data can be read
+    /// than the caller can hold in memory at once.
+    ///
+    /// `read_len` bytes are received in total, and they are delivered to
+    /// `on_chunk` in order through the given `chunk` buffer: each call passes
+    /// a full buffer except possibly the last. `read_len` is not limited to
+    /// 4096 bytes; longer reads are split into several `write_then_read`
+    /// commands, with `write_from` sent only at the start of the first. The
+    /// write data is still limited to 4096 bytes, and the `Request` error is
+    /// returned if `chunk` is empty when there is data to read.
+    ///
+    /// Because the read may span several commands, the chip select signal
+    /// is not controlled by the Bus Pirate as in `write_then_read`. Instead,
+    /// if `cs` is true then this method activates chip select before the
+    /// first command and deactivates it after the last, so that it remains
+    /// active throughout. Chip select is deactivated even if the operation
+    /// fails, and isn't activated at all if the arguments are invalid.
+    pub fn write_then_read_chunked<F>(
+        &mut self,
+        write_from: &[u8],
+        read_len: usize,
+        chunk: &mut [u8],
+        cs: bool,
+        on_chunk: F,
+    ) -> Result<(), Error<TXErr, RXErr>>
+    where
+        F: FnMut(&[u8]),
+    {
+        if write_from.len() > WRITE_THEN_READ_MAX {
             return Err(Error::Request); // Too many bytes to send
         }
-        if read_into.len() > 4096 {
+        if read_len > 0 && chunk.is_empty() {
+            return Err(Error::Request); // Nowhere to put the received data
+        }
+        if !cs {
+            return self.write_then_read_chunked_inner(write_from, read_len, chunk, on_chunk);
+        }
+
+        self.chip_select(true)?;
+        let result = self.write_then_read_chunked_inner(write_from, read_len, chunk, on_chunk);
+        let deselect = self.chip_select(false);
+        result?;
+        deselect
+    }
+
+    /// `write_then_read_chunked_inner` implements `write_then_read_chunked`
+    /// without any chip select handling.
+    fn write_then_read_chunked_inner<F>(
+        &mut self,
+        write_from: &[u8],
+        read_len: usize,
+        chunk: &mut [u8],
+        mut on_chunk: F,
+    ) -> Result<(), Error<TXErr, RXErr>>
+    where
+        F: FnMut(&[u8]),
+    {
+        let mut write_from = write_from;
+        let mut remain = read_len;
+        let mut filled = 0;
+        loop {
+            let cmd_len = if remain > WRITE_THEN_READ_MAX {
+                WRITE_THEN_READ_MAX
+            } else {
+                remain
+            };
+            self.start_write_then_read(
+                write_from.iter().copied(),
+                write_from.len(),
+                cmd_len,
+                false,
+            )?;
+            write_from = &[]; // Only the first command writes anything.
+
+            for _ in 0..cmd_len {
+                chunk[filled] = self.ch.read_data()?;
+                filled += 1;
+                if filled == chunk.len() {
+                    on_chunk(chunk);
+                    filled = 0;
+                }
+            }
+
+            remain -= cmd_len;
+            if remain == 0 {
+                break;
+            }
+        }
+        if filled > 0 {
+            on_chunk(&chunk[..filled]);
+        }
+        Ok(())
+    }
+
+    /// `transfer_bulk` sends a bulk transfer command for between 1 and 16 bytes,
+    /// overwriting the given bytes with the bytes received. The caller must
+    /// check the length.
+    fn transfer_bulk(&mut self, v: &mut [u8]) -> Result<(), Error<TXErr, RXErr>> {
+        self.check_cs()?;
+        self.ch.write(bulk_transfer_command(v.len()))?;
+        for i in 0..v.len() {
+            self.ch.write(v[i])?;
+        }
+        self.ch.flush_command()?;
+
+        match self.ch.read_data()? {
+            0x01 => (),
+            got => {
+                return Err(Error::UnexpectedByte {
+                    expected: 0x01,
+                    got,
+                })
+            }
+        }
+
+        for i in 0..v.len() {
+            v[i] = self.ch.read_data()?;
+        }
+
+        Ok(())
+    }
+
+    /// `verify_sync` asks the Bus Pirate for its SPI mode version string and
+    /// returns the `Protocol` error unless exactly that string is received,
+    /// discarding any other buffered data in that case.
+    fn verify_sync(&mut self) -> Result<(), Error<TXErr, RXErr>> {
+        self.ch.write(0b00000001)?;
+        self.ch.flush_command()?;
+
+        let mut got = [0_u8; 4];
+        for c in got.iter_mut() {
+            *c = self.ch.read_data()?;
+        }
+        if got != crate::PROTO_SPI_VERSION_MSG || self.ch.has_pending()? {
+            // Discard whatever is left, to give the next command a chance.
+            self.ch.eat_rx_buffer()?;
+            return Err(Error::Protocol);
+        }
+        Ok(())
+    }
+
+    /// `check_cs` returns the `Request` error if strict chip select checking
+    /// is enabled and chip select is not active.
+    fn check_cs(&self) -> Result<(), Error<TXErr, RXErr>> {
+        if self.strict_cs && !self.cs_active {
+            return Err(Error::Request); // Chip select must be active first
+        }
+        Ok(())
+    }
+
+    /// `start_write_then_read` sends a "write then read" command along with
+    /// the data to write, and then waits for the Bus Pirate to acknowledge it.
+    /// The caller must then read the given number of bytes.
+    ///
+    /// Exactly `write_len` bytes are taken from `write_from`, which must
+    /// produce at least that many.
+    fn start_write_then_read<I>(
+        &mut self,
+        write_from: I,
+        write_len: usize,
+        read_len: usize,
+        cs: bool,
+    ) -> Result<(), Error<TXErr, RXErr>>
+    where
+        I: Iterator<Item = u8>,
+    {
+        if write_len > WRITE_THEN_READ_MAX {
+            return Err(Error::Request); // Too many bytes to send
+        }
+        if read_len > WRITE_THEN_READ_MAX {
             return Err(Error::Request); // Too many bytes to read
         }
+        if !cs {
+            self.check_cs()?;
+        }
 
-        let wr_len = write_from.len() as u16;
-        let rd_len = read_into.len() as u16;
+        let wr_len = write_len as u16;
+        let rd_len = read_len as u16;
         self.ch.write(if cs { 0b00000100 } else { 0b00000101 })?;
         self.ch.write((wr_len >> 8) as u8)?; // MSB of length to write
         self.ch.write(wr_len as u8)?; // LSB of length to write
         self.ch.write((rd_len >> 8) as u8)?; // MSB of length to read
         self.ch.write(rd_len as u8)?; // LSB of length to read
 
-        for c in write_from {
-            self.ch.write(*c)?;
+        for c in write_from.take(write_len) {
+            self.ch.write(c)?;
         }
+        self.ch.flush_command()?;
 
-        match self.ch.read()? {
-            0x01 => (),
-            _ => return Err(Error::<TXErr, RXErr>::Protocol),
-        }
-
-        for i in 0..read_into.len() {
-            read_into[i] = self.ch.read()?;
+        match self.ch.read_data()? {
+            0x01 => Ok(()),
+            got => Err(Error::UnexpectedByte {
+                expected: 0x01,
+                got,
+            }),
         }
+    }
+}
 
-        Ok(())
+impl<TX, RX> crate::sealed::Sealed<TX, RX> for SPI<TX, RX>
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+{
+    #[allow(private_interfaces)]
+    fn from_channel(ch: low::Channel<TX, RX>) -> Self {
+        Self::new(ch)
     }
 }
 
+impl<TX, RX> crate::ModeMarker<TX, RX> for SPI<TX, RX>
+where
+    TX: serial::Write<u8>,
+    RX: serial::Read<u8>,
+{
+    const MODE: crate::Mode = crate::Mode::SPI;
+    const VERSION_MSG: [u8; 4] = crate::PROTO_SPI_VERSION_MSG;
+    const ENTER_COMMAND: u8 = 0b00000001;
+}
+
+/// `bulk_transfer_command` returns the command byte for a bulk transfer of
+/// `len` bytes, which must be between 1 and 16.
+///
+/// The length is encoded in the low four bits as `len - 1`, so a 1-byte
+/// transfer is `0b00010000` and a 16-byte transfer is `0b00011111`.
+fn bulk_transfer_command(len: usize) -> u8 {
+    debug_assert!(
+        (1..=16).contains(&len),
+        "invalid SPI bulk transfer length {}",
+        len
+    );
+    0b00010000 | ((len - 1) as u8 & 0b1111)
+}
+
+/// `TransferLen` allows `transfer_array` to reject arrays that are too long
+/// for a single bulk transfer command at compile time.
+struct TransferLen<const N: usize>;
+
+impl<const N: usize> TransferLen<N> {
+    const CHECK: () = assert!(N <= 16, "SPI bulk transfers are limited to 16 bytes");
+}
+
 /// `Speed` describes a clock speed to be used for Bus Pirate SPI data transfers.
+///
+/// The Bus Pirate's binary SPI protocol supports only these eight speeds,
+/// and has no way to select other clock divisors. To target a particular
+/// rate, use `ClockRate::from_hz` to choose the fastest speed that does not
+/// exceed it, or `ClockRate::closest` to choose the nearest speed. For
+/// example, a device with a maximum clock rate of 3MHz must use
+/// `Speed2_6MHz`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Speed {
     Speed30KHz,
     Speed125KHz,
@@ -198,24 +1125,42 @@ pub enum Speed {
     Speed8MHz,
 }
 
-/// `PinOutput` describes an output mode to be used for Bus Pirate SPI data
-/// transfers.
-pub enum PinOutput {
-    // `PinOutputHiZ` requests that the Bus Pirate set its outputs to a high
-    // impedance state when signalling "active".
-    PinOutputHiZ,
-    // `PinOutputHiZ` requests that the Bus Pirate drive its outputs to 3.3V
-    // when signalling "active".
-    PinOutput3_3V,
+impl ClockRate for Speed {
+    const RATES: &'static [Self] = &[
+        Speed::Speed30KHz,
+        Speed::Speed125KHz,
+        Speed::Speed250KHz,
+        Speed::Speed1MHz,
+        Speed::Speed2MHz,
+        Speed::Speed2_6MHz,
+        Speed::Speed4MHz,
+        Speed::Speed8MHz,
+    ];
+
+    fn to_hz(&self) -> u32 {
+        use Speed::*;
+        match self {
+            Speed30KHz => 30_000,
+            Speed125KHz => 125_000,
+            Speed250KHz => 250_000,
+            Speed1MHz => 1_000_000,
+            Speed2MHz => 2_000_000,
+            Speed2_6MHz => 2_600_000,
+            Speed4MHz => 4_000_000,
+            Speed8MHz => 8_000_000,
+        }
+    }
 }
 
 /// `ClockPhase` describes a single phase of an SPI transmission clock cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ClockPhase {
     ClockPhaseHigh,
     ClockPhaseLow,
 }
 
 /// `ClockEdge` describes a single transition edge of an SPI transmission clock cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ClockEdge {
     ClockEdgeFalling,
     ClockEdgeRising,
@@ -223,12 +1168,14 @@ pub enum ClockEdge {
 
 /// `SampleTime` describes a point within an SPI transmission where data bits
 /// are to be sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SampleTime {
     SampleTimeMiddle,
     SampleTimeEnd,
 }
 
 /// `Config` describes SPI-specific Bus Pirate settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Config {
     pub pin_output: PinOutput,
     pub clock_idle_phase: ClockPhase,
@@ -236,6 +1183,27 @@ pub struct Config {
     pub sample_time: SampleTime,
 }
 
+/// `ConfigSnapshot` describes the settings most recently applied to an `SPI`
+/// object, as returned by `SPI::config_snapshot`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigSnapshot {
+    /// `speed` is the clock rate set by `set_speed`, or `None` if it has not
+    /// been set since entering SPI mode.
+    pub speed: Option<Speed>,
+
+    /// `config` is the configuration set by `set_config`, or `None` if it
+    /// has not been set since entering SPI mode.
+    pub config: Option<Config>,
+
+    /// `peripherals` is the peripheral configuration set by
+    /// `configure_peripherals`, which is initially everything switched off.
+    pub peripherals: crate::peripherals::Config,
+
+    /// `cs_active` is true if chip select was most recently activated using
+    /// `chip_select`.
+    pub cs_active: bool,
+}
+
 /// `DEFAULT_CONFIG` is the initial state of SPI configuration when a Bus Pirate
 /// is first started, according to the Bus Pirate documentation.
 pub const DEFAULT_CONFIG: Config = Config {
@@ -245,14 +1213,53 @@ pub const DEFAULT_CONFIG: Config = Config {
     sample_time: SampleTime::SampleTimeMiddle,
 };
 
+/// The default `Config` is `DEFAULT_CONFIG`.
+impl Default for Config {
+    fn default() -> Self {
+        DEFAULT_CONFIG
+    }
+}
+
 impl Config {
-    pub(crate) fn command_byte(&self) -> u8 {
+    /// `from_command_byte` decodes a configuration from the command byte that
+    /// `command_byte` would produce for it.
+    ///
+    /// The upper four bits of an SPI configuration command are always
+    /// `1000`, so any other value is not a valid configuration and the result
+    /// is `None`.
+    pub fn from_command_byte(cmd: u8) -> Option<Config> {
+        if cmd & 0b11110000 != 0b10000000 {
+            return None;
+        }
+        Some(Config {
+            pin_output: if cmd & (1 << 3) != 0 {
+                PinOutput::PinOutput3_3V
+            } else {
+                PinOutput::PinOutputHiZ
+            },
+            clock_idle_phase: if cmd & (1 << 2) != 0 {
+                ClockPhase::ClockPhaseHigh
+            } else {
+                ClockPhase::ClockPhaseLow
+            },
+            clock_edge: if cmd & (1 << 1) != 0 {
+                ClockEdge::ClockEdgeFalling
+            } else {
+                ClockEdge::ClockEdgeRising
+            },
+            sample_time: if cmd & 1 != 0 {
+                SampleTime::SampleTimeEnd
+            } else {
+                SampleTime::SampleTimeMiddle
+            },
+        })
+    }
+
+    /// `command_byte` returns the SPI configuration command that selects this
+    /// configuration, as sent by `SPI::set_config`.
+    pub fn command_byte(&self) -> u8 {
         let mut cmd = 0b10000000 as u8;
-        cmd = cmd
-            | (match self.pin_output {
-                PinOutput::PinOutputHiZ => 0,
-                PinOutput::PinOutput3_3V => 1,
-            } << 3);
+        cmd = cmd | (self.pin_output.config_bit() << 3);
         cmd = cmd
             | (match self.clock_idle_phase {
                 ClockPhase::ClockPhaseLow => 0,
@@ -300,6 +1307,48 @@ pub trait Comms {
         read_into: &'w mut [u8],
         cs: bool,
     ) -> Result<(), Self::Error>;
+
+    /// `write_only` sends up to 4096 bytes of data, discarding anything
+    /// received, and optionally activates the chip select signal for the
+    /// duration of the operation.
+    ///
+    /// This is equivalent to `transaction` with nothing to read. If the given
+    /// slice is longer than 4096 elements, `write_only` returns the `Request`
+    /// error.
+    fn write_only(&mut self, write_from: &[u8], cs: bool) -> Result<(), Self::Error> {
+        self.transaction(write_from, &mut [], cs)
+    }
+
+    /// `transaction_cs` activates the chip select signal, calls the given
+    /// function, and then deactivates the chip select signal again.
+    ///
+    /// This allows performing several transfers within a single activation of
+    /// chip select. Chip select is deactivated even if the given function
+    /// fails, in which case the function's error is returned.
+    ///
+    /// This is available only for implementations that can also control chip
+    /// select separately, by implementing `ChipSelect`.
+    fn transaction_cs<R, F>(&mut self, f: F) -> Result<R, Self::Error>
+    where
+        Self: ChipSelect + Sized,
+        F: FnOnce(&mut Self) -> Result<R, Self::Error>,
+    {
+        ChipSelect::chip_select(self, true)?;
+        let result = f(self);
+        let deselect = ChipSelect::chip_select(self, false);
+        let ret = result?;
+        deselect?;
+        Ok(ret)
+    }
+}
+
+/// `ChipSelect` is a trait implemented by `SPI` and other `Comms`
+/// implementations that can control the chip select signal independently
+/// of any transfer, as required by `Comms::transaction_cs`.
+pub trait ChipSelect: Comms {
+    /// `chip_select` sets the state of the chip select signal, with `true`
+    /// representing the active (electrically low) state.
+    fn chip_select(&mut self, active: bool) -> Result<(), Self::Error>;
 }
 
 impl<TX, RX, TXErr, RXErr> Comms for SPI<TX, RX>
@@ -329,3 +1378,13 @@ where
         self.write_then_read(write_from, read_into, cs)
     }
 }
+
+impl<TX, RX, TXErr, RXErr> ChipSelect for SPI<TX, RX>
+where
+    TX: embedded_hal::serial::Write<u8, Error = TXErr>,
+    RX: embedded_hal::serial::Read<u8, Error = RXErr>,
+{
+    fn chip_select(&mut self, active: bool) -> Result<(), Self::Error> {
+        SPI::chip_select(self, active)
+    }
+}
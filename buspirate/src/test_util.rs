@@ -0,0 +1,347 @@
+//! Module `test_util` contains fake serial ports for testing code that uses
+//! this library without a real Bus Pirate.
+//!
+//! A `FakeSerial` follows a script of the bytes the library is expected to
+//! write and the bytes to reply with. The replies are made available to read
+//! only once all of the writes scripted before them have happened, which
+//! mimics a Bus Pirate responding to each command in turn. Writing anything
+//! other than the next expected byte causes a panic, so a test fails as soon
+//! as the library deviates from the script.
+//!
+//! ```rust,ignore
+//! let mut fake = FakeSerial::new();
+//! fake.expect(&[0b00010000, 0xaa]).reply(&[0x01, 0x55]);
+//! let (tx, rx) = fake.split();
+//! // ...use tx and rx with this library...
+//! fake.assert_done();
+//! ```
+//!
+//! `MockChannel` is a looser alternative that accepts any writes and records
+//! them, so that a test can check the exact bytes sent by a single command.
+//!
+//! This module is available only when the `test-util` feature is enabled,
+//! and it requires the standard library.
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+use embedded_hal::serial;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::vec::Vec;
+
+/// `FakeSerial` is a scripted fake serial port.
+///
+/// The transmit and receive halves returned by `split` share the script, so
+/// the `FakeSerial` object can still be used to check progress after they
+/// have been passed to the library.
+#[derive(Debug, Default)]
+pub struct FakeSerial {
+    state: Rc<RefCell<State>>,
+}
+
+/// `FakeTx` is the transmit half of a `FakeSerial`.
+#[derive(Debug)]
+pub struct FakeTx {
+    state: Rc<RefCell<State>>,
+}
+
+/// `FakeRx` is the receive half of a `FakeSerial`.
+#[derive(Debug)]
+pub struct FakeRx {
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    steps: VecDeque<Step>,
+    rx: VecDeque<u8>,
+    written: usize,
+}
+
+#[derive(Debug)]
+enum Step {
+    Expect(VecDeque<u8>),
+    Reply(Vec<u8>),
+    FailFlush,
+}
+
+/// `FakeError` is the error returned by a `FakeTx` when a scripted failure
+/// is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FakeError;
+
+impl FakeSerial {
+    /// `FakeSerial::new` creates a fake serial port with an empty script.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `expect` adds to the script some bytes the library must write next.
+    pub fn expect(&mut self, bytes: &[u8]) -> &mut Self {
+        let step = Step::Expect(bytes.iter().copied().collect());
+        self.state.borrow_mut().steps.push_back(step);
+        self
+    }
+
+    /// `reply` adds to the script some bytes that can be read once all of
+    /// the previously-expected bytes have been written.
+    pub fn reply(&mut self, bytes: &[u8]) -> &mut Self {
+        let step = Step::Reply(bytes.to_vec());
+        self.state.borrow_mut().steps.push_back(step);
+        self
+    }
+
+    /// `fail_flush` adds to the script a failure of the next flush, which
+    /// returns `FakeError`.
+    ///
+    /// The failure happens only once all of the previously-expected bytes
+    /// have been written, and any flushes before then succeed as normal.
+    pub fn fail_flush(&mut self) -> &mut Self {
+        self.state.borrow_mut().steps.push_back(Step::FailFlush);
+        self
+    }
+
+    /// `split` returns transmit and receive objects that follow the script.
+    pub fn split(&self) -> (FakeTx, FakeRx) {
+        self.state.borrow_mut().release();
+        (
+            FakeTx {
+                state: self.state.clone(),
+            },
+            FakeRx {
+                state: self.state.clone(),
+            },
+        )
+    }
+
+    /// `assert_done` panics unless the whole script has been followed and
+    /// all of the replies have been read.
+    pub fn assert_done(&self) {
+        let state = self.state.borrow();
+        assert!(
+            state.steps.is_empty(),
+            "script not completed after {} bytes written; remaining steps: {:?}",
+            state.written,
+            state.steps,
+        );
+        assert!(
+            state.rx.is_empty(),
+            "{} reply bytes were not read: {:?}",
+            state.rx.len(),
+            state.rx,
+        );
+    }
+}
+
+impl State {
+    /// `release` discards completed expectations and makes available to read
+    /// any replies that are no longer waiting for expected writes.
+    fn release(&mut self) {
+        loop {
+            match self.steps.front() {
+                Some(Step::Expect(bytes)) if bytes.is_empty() => (),
+                Some(Step::Reply(_)) => (),
+                _ => return,
+            }
+            if let Some(Step::Reply(bytes)) = self.steps.pop_front() {
+                self.rx.extend(bytes);
+            }
+        }
+    }
+}
+
+impl serial::Write<u8> for FakeTx {
+    type Error = FakeError;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        if std::thread::panicking() {
+            // Don't turn a failed test into an abort when the library's
+            // reset-on-drop behavior writes during unwinding.
+            return Ok(());
+        }
+        let mut state = self.state.borrow_mut();
+        let offset = state.written;
+        let want = match state.steps.front_mut() {
+            Some(Step::Expect(bytes)) => bytes.pop_front(),
+            _ => None,
+        };
+        match want {
+            Some(want) => assert_eq!(
+                word, want,
+                "wrong byte written at offset {}: got {:#010b}, want {:#010b}",
+                offset, word, want,
+            ),
+            None => panic!(
+                "unexpected byte written at offset {}: {:#010b}",
+                offset, word
+            ),
+        }
+        state.written += 1;
+        state.release();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        let mut state = self.state.borrow_mut();
+        if let Some(Step::FailFlush) = state.steps.front() {
+            state.steps.pop_front();
+            state.release();
+            return Err(nb::Error::Other(FakeError));
+        }
+        Ok(())
+    }
+}
+
+impl serial::Read<u8> for FakeRx {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        match self.state.borrow_mut().rx.pop_front() {
+            Some(c) => Ok(c),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+}
+
+/// `MockChannel` is a fake serial port that records every byte written to
+/// it, for tests that are interested only in what the library sends.
+///
+/// Unlike `FakeSerial`, a `MockChannel` accepts any writes, and its replies
+/// depend only on how many bytes have been written. Tests can queue the
+/// acknowledgements that the commands under test will wait for, run them,
+/// and then check the exact bytes sent:
+///
+/// ```rust,ignore
+/// let mut mock = MockChannel::new();
+/// mock.reply(&[0x01]);
+/// spi.set_speed(Speed::Speed2MHz)?;
+/// mock.assert_written(&[0b01100100]);
+/// ```
+#[derive(Debug, Default)]
+pub struct MockChannel {
+    state: Rc<RefCell<MockState>>,
+}
+
+/// `MockTx` is the transmit half of a `MockChannel`.
+#[derive(Debug)]
+pub struct MockTx {
+    state: Rc<RefCell<MockState>>,
+}
+
+/// `MockRx` is the receive half of a `MockChannel`.
+#[derive(Debug)]
+pub struct MockRx {
+    state: Rc<RefCell<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    written: Vec<u8>,
+    total: usize,
+    pending: VecDeque<(usize, Vec<u8>)>,
+    rx: VecDeque<u8>,
+}
+
+impl MockChannel {
+    /// `MockChannel::new` creates a mock serial port with nothing written and
+    /// no replies queued.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `reply` queues some bytes to be read as soon as any earlier replies
+    /// have become readable.
+    pub fn reply(&mut self, bytes: &[u8]) -> &mut Self {
+        self.reply_after(0, bytes)
+    }
+
+    /// `reply_after` queues some bytes that become readable only once the
+    /// given number of further bytes have been written after any earlier
+    /// replies became readable.
+    ///
+    /// This allows scripting a response to a particular command in a longer
+    /// sequence, such as the mode entry handshakes, without the library
+    /// seeing it too soon and discarding it.
+    pub fn reply_after(&mut self, writes: usize, bytes: &[u8]) -> &mut Self {
+        {
+            let mut state = self.state.borrow_mut();
+            let after = match state.pending.back() {
+                Some((after, _)) => *after,
+                None => state.total,
+            };
+            state.pending.push_back((after + writes, bytes.to_vec()));
+        }
+        self
+    }
+
+    /// `split` returns transmit and receive objects that use this mock.
+    pub fn split(&self) -> (MockTx, MockRx) {
+        (
+            MockTx {
+                state: self.state.clone(),
+            },
+            MockRx {
+                state: self.state.clone(),
+            },
+        )
+    }
+
+    /// `written` returns all of the bytes written since the mock was created
+    /// or since they were last taken using `take_written`.
+    pub fn written(&self) -> Vec<u8> {
+        self.state.borrow().written.clone()
+    }
+
+    /// `take_written` is like `written`, but also forgets the bytes so that a
+    /// test can check each step separately.
+    pub fn take_written(&self) -> Vec<u8> {
+        core::mem::take(&mut self.state.borrow_mut().written)
+    }
+
+    /// `assert_written` panics unless exactly the given bytes have been
+    /// written since the last check, and then forgets them.
+    pub fn assert_written(&self, want: &[u8]) {
+        let got = self.take_written();
+        assert!(
+            got == want,
+            "wrong bytes written\n got: {:02x?}\nwant: {:02x?}",
+            got,
+            want,
+        );
+    }
+}
+
+impl serial::Write<u8> for MockTx {
+    type Error = Infallible;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        let mut state = self.state.borrow_mut();
+        state.written.push(word);
+        state.total += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl serial::Read<u8> for MockRx {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        let mut state = self.state.borrow_mut();
+        while let Some((after, _)) = state.pending.front() {
+            if *after > state.total {
+                break;
+            }
+            if let Some((_, bytes)) = state.pending.pop_front() {
+                state.rx.extend(bytes);
+            }
+        }
+        match state.rx.pop_front() {
+            Some(c) => Ok(c),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use buspirate::bitbang::BitBang;
+use buspirate::peripherals;
+use buspirate::spi::{Speed, DEFAULT_CONFIG, SPI};
+use buspirate::test_util::{MockChannel, MockRx, MockTx};
+use buspirate::BusPirate;
+
+/// `bitbang` enters bit-bang mode using the given mock, and then forgets the
+/// bytes written while doing so.
+fn bitbang(mock: &mut MockChannel) -> BitBang<MockTx, MockRx> {
+    // The terminal reset sequence is twelve bytes, followed by a null byte
+    // to enter bit-bang mode.
+    mock.reply_after(13, b"BBIO1");
+    let (tx, rx) = mock.split();
+    let mut bb = BusPirate::new(tx, rx).to_bitbang().unwrap();
+    bb.forget_reset();
+    mock.take_written();
+    bb
+}
+
+/// `spi` enters SPI mode using the given mock, and then forgets the bytes
+/// written while doing so.
+fn spi(mock: &mut MockChannel) -> SPI<MockTx, MockRx> {
+    // The terminal reset sequence is twelve bytes, followed by a null byte
+    // to enter bit-bang mode and then the SPI mode command.
+    mock.reply_after(13, b"BBIO1").reply_after(1, b"SPI1");
+    let (tx, rx) = mock.split();
+    let mut spi = BusPirate::new(tx, rx)
+        .to_bitbang()
+        .unwrap()
+        .to_spi()
+        .unwrap();
+    spi.forget_reset();
+    mock.take_written();
+    spi
+}
+
+#[test]
+fn mode_entry() {
+    let mut mock = MockChannel::new();
+    mock.reply_after(13, b"BBIO1").reply_after(1, b"SPI1");
+    let (tx, rx) = mock.split();
+    let spi = BusPirate::new(tx, rx)
+        .to_bitbang()
+        .unwrap()
+        .to_spi()
+        .unwrap();
+    spi.close().unwrap();
+
+    let mut want = vec![b'\n'; 10];
+    want.extend(&[b'#', b'\n', 0x00, 0b00000001, 0b00001111]);
+    mock.assert_written(&want);
+}
+
+#[test]
+fn set_speed() {
+    let mut mock = MockChannel::new();
+    let mut spi = spi(&mut mock);
+
+    mock.reply(&[0x01]);
+    spi.set_speed(Speed::Speed2MHz).unwrap();
+    mock.assert_written(&[0b01100100]);
+}
+
+#[test]
+fn chip_select() {
+    let mut mock = MockChannel::new();
+    let mut spi = spi(&mut mock);
+
+    mock.reply(&[0x01, 0x01]);
+    spi.chip_select(true).unwrap();
+    spi.chip_select(false).unwrap();
+    mock.assert_written(&[0b00000010, 0b00000011]);
+}
+
+#[test]
+fn configure_peripherals() {
+    let mut mock = MockChannel::new();
+    let mut spi = spi(&mut mock);
+
+    mock.reply(&[0x01, 0x01, 0x01]);
+    spi.configure_peripherals(peripherals::Config::default())
+        .unwrap();
+    spi.configure_peripherals(peripherals::Config {
+        power_supply: true,
+        ..Default::default()
+    })
+    .unwrap();
+    spi.configure_peripherals(peripherals::Config {
+        power_supply: true,
+        pull_ups: true,
+        aux: true,
+        cs: true,
+    })
+    .unwrap();
+    mock.assert_written(&[0b01000000, 0b01001000, 0b01001111]);
+}
+
+#[test]
+fn set_speed_all() {
+    let cases = [
+        (Speed::Speed30KHz, 0b01100000),
+        (Speed::Speed125KHz, 0b01100001),
+        (Speed::Speed250KHz, 0b01100010),
+        (Speed::Speed1MHz, 0b01100011),
+        (Speed::Speed2MHz, 0b01100100),
+        (Speed::Speed2_6MHz, 0b01100101),
+        (Speed::Speed4MHz, 0b01100110),
+        (Speed::Speed8MHz, 0b01100111),
+    ];
+
+    let mut mock = MockChannel::new();
+    let mut spi = spi(&mut mock);
+    for (speed, want) in cases.iter() {
+        mock.reply(&[0x01]);
+        spi.set_speed(*speed).unwrap();
+        mock.assert_written(&[*want]);
+    }
+}
+
+#[test]
+fn set_speed_distinct_from_peripherals() {
+    use buspirate::ClockRate;
+
+    // The speed and peripherals commands share their upper bits, so a
+    // mistake in either encoding can turn a speed change into a change of
+    // power supply or pull-ups.
+    let mut mock = MockChannel::new();
+    let mut spi = spi(&mut mock);
+    for speed in Speed::RATES.iter() {
+        mock.reply(&[0x01]);
+        spi.set_speed(*speed).unwrap();
+        let got = mock.take_written();
+        for bits in 0..16_u8 {
+            let config = peripherals::Config {
+                power_supply: bits & 0b1000 != 0,
+                pull_ups: bits & 0b0100 != 0,
+                aux: bits & 0b0010 != 0,
+                cs: bits & 0b0001 != 0,
+            };
+            assert_ne!(got, [config.command_byte()], "{:?} vs {:?}", speed, config);
+        }
+    }
+}
+
+#[test]
+fn set_config_default() {
+    let mut mock = MockChannel::new();
+    let mut spi = spi(&mut mock);
+
+    // HiZ outputs, clock idle low, active-to-idle (falling) clock edge and
+    // sampling in the middle: that's SPI mode 0, with only the CKE bit set.
+    mock.reply(&[0x01]);
+    spi.set_config(DEFAULT_CONFIG).unwrap();
+    mock.assert_written(&[0b10000010]);
+}
+
+#[test]
+fn repeat_byte() {
+    let mut mock = MockChannel::new();
+    let mut spi = spi(&mut mock);
+
+    mock.reply(&[0x01, 0x01]);
+    spi.repeat_byte(0xff, 4099).unwrap();
+    let mut want = vec![0b00000101, 0x10, 0x00, 0x00, 0x00];
+    want.extend(vec![0xff; 4096]);
+    want.extend(&[0b00000101, 0x00, 0x03, 0x00, 0x00, 0xff, 0xff, 0xff]);
+    mock.assert_written(&want);
+}
+
+#[test]
+fn speed_from_hz() {
+    use buspirate::ClockRate;
+
+    assert_eq!(Speed::from_hz(3_000_000), Speed::Speed2_6MHz);
+    assert_eq!(Speed::from_hz(10), Speed::Speed30KHz);
+    assert_eq!(Speed::closest(3_500_000), Speed::Speed4MHz);
+    assert_eq!(Speed::closest(100_000), Speed::Speed125KHz);
+    assert_eq!(Speed::closest(20_000_000), Speed::Speed8MHz);
+}
+
+/// `Delay` is a fake delay implementation that records the total time it
+/// was asked to wait.
+struct Delay(u32);
+
+impl embedded_hal::blocking::delay::DelayUs<u16> for Delay {
+    fn delay_us(&mut self, us: u16) {
+        self.0 += us as u32;
+    }
+}
+
+#[test]
+fn write_delay_read() {
+    let mut mock = MockChannel::new();
+    let mut spi = spi(&mut mock);
+
+    mock.reply(&[0x01, 0x01, 0x01, 0xa1, 0xa2, 0x01]);
+    let mut delay = Delay(0);
+    let mut buf = [0; 2];
+    spi.write_delay_read(&[0x03], &mut buf, true, &mut delay, 50)
+        .unwrap();
+    assert_eq!(buf, [0xa1, 0xa2]);
+    assert_eq!(delay.0, 50);
+    mock.assert_written(&[
+        0b00000010, // chip select active
+        0b00000101, 0x00, 0x01, 0x00, 0x00, 0x03, // write one byte
+        0b00000101, 0x00, 0x00, 0x00, 0x02,       // read two bytes
+        0b00000011, // chip select inactive
+    ]);
+}
+
+#[test]
+fn write_only() {
+    use buspirate::spi::Comms;
+
+    let mut mock = MockChannel::new();
+    let mut spi = spi(&mut mock);
+
+    // The acknowledgement is the only reply, and it is consumed.
+    mock.reply(&[0x01]);
+    spi.write_only(&[0x06, 0x07], true).unwrap();
+    mock.assert_written(&[0b00000100, 0x00, 0x02, 0x00, 0x00, 0x06, 0x07]);
+    assert!(!spi.has_pending().unwrap());
+
+    mock.reply(&[0x01]);
+    spi.write_only(&[0x06], false).unwrap();
+    mock.assert_written(&[0b00000101, 0x00, 0x01, 0x00, 0x00, 0x06]);
+    assert!(!spi.has_pending().unwrap());
+}
+
+#[test]
+fn pulse_cs() {
+    let mut mock = MockChannel::new();
+    let mut spi = spi(&mut mock);
+
+    mock.reply(&[0x01, 0x01]);
+    let mut delay = Delay(0);
+    spi.pulse_cs(&mut delay, 10).unwrap();
+    assert_eq!(delay.0, 10);
+    mock.assert_written(&[0b00000010, 0b00000011]);
+}
+
+#[test]
+fn registers() {
+    let mut mock = MockChannel::new();
+    let mut spi = spi(&mut mock);
+
+    mock.reply(&[0x01, 0x12, 0x34]);
+    assert_eq!(spi.read_reg_u16_be(0x8f).unwrap(), 0x1234);
+    mock.assert_written(&[0b00000100, 0x00, 0x01, 0x00, 0x02, 0x8f]);
+
+    mock.reply(&[0x01, 0x12, 0x34]);
+    assert_eq!(spi.read_reg_u16_le(0x8f).unwrap(), 0x3412);
+    mock.assert_written(&[0b00000100, 0x00, 0x01, 0x00, 0x02, 0x8f]);
+
+    mock.reply(&[0x01]);
+    spi.write_reg_u16_le(0x0f, 0x1234).unwrap();
+    mock.assert_written(&[0b00000100, 0x00, 0x03, 0x00, 0x00, 0x0f, 0x34, 0x12]);
+}
+
+#[test]
+fn update_pins() {
+    use buspirate::bitbang::{PinId, PinLevels};
+
+    let mut mock = MockChannel::new();
+    let mut bb = bitbang(&mut mock);
+
+    let levels = PinLevels::default()
+        .with(PinId::Mosi, true)
+        .toggle(PinId::Clk);
+    assert!(levels.get(PinId::Clk));
+    assert!(!levels.get(PinId::Cs));
+
+    mock.reply(&[0b10001100, 0b10000100]);
+    bb.set_pins(levels).unwrap();
+    let got = bb.update_pins(|pins| pins.toggle(PinId::Mosi)).unwrap();
+    assert_eq!(got, PinLevels::default().with(PinId::Clk, true));
+    mock.assert_written(&[0b10001100, 0b10000100]);
+}
+
+#[test]
+fn config_from_command_byte() {
+    use buspirate::spi::Config;
+
+    for cmd in 0b10000000..=0b10001111 {
+        let config = Config::from_command_byte(cmd).unwrap();
+        assert_eq!(config.command_byte(), cmd);
+    }
+    assert_eq!(
+        Config::from_command_byte(DEFAULT_CONFIG.command_byte()),
+        Some(DEFAULT_CONFIG)
+    );
+    assert_eq!(Config::from_command_byte(0b10010000), None);
+    assert_eq!(Config::from_command_byte(0b00001000), None);
+}
+
+#[test]
+fn measure_voltages() {
+    let mut mock = MockChannel::new();
+    let mut bb = bitbang(&mut mock);
+
+    mock.reply(&[0x01, 0x23, 0x00, 0x45, 0x03, 0xff]);
+    let mut samples = [0; 3];
+    bb.measure_voltages(&mut samples).unwrap();
+    assert_eq!(samples, [0x0123, 0x0045, 0x03ff]);
+    mock.assert_written(&[0b00010100; 3]);
+}
@@ -0,0 +1,107 @@
+//! Tests of the runtime mode tracking in `dynamic::Device`, against a
+//! scripted fake serial port.
+
+#![cfg(feature = "dynamic")]
+
+use buspirate::dynamic::Device;
+use buspirate::test_util::{FakeRx, FakeSerial, FakeTx};
+use buspirate::Error;
+
+const TERMINAL_RESET: &[u8] = b"\n\n\n\n\n\n\n\n\n\n#\n";
+
+fn device(fake: &FakeSerial) -> Device<FakeTx, FakeRx> {
+    let (tx, rx) = fake.split();
+    Device::new(tx, rx)
+}
+
+fn assert_request<T: core::fmt::Debug>(
+    result: Result<T, Error<impl core::fmt::Debug, impl core::fmt::Debug>>,
+) {
+    match result {
+        Err(Error::Request) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn spi_session() {
+    let mut fake = FakeSerial::new();
+    fake.expect(TERMINAL_RESET)
+        .reply(b"HiZ>")
+        .expect(&[0x00])
+        .reply(b"BBIO1")
+        .expect(&[0b00000001])
+        .reply(b"SPI1")
+        .expect(&[0b00010001, 0x12, 0x34])
+        .reply(&[0x01, 0xa1, 0xa2])
+        .expect(&[0b00001111]);
+
+    let mut dev = device(&fake);
+    dev.enter_spi().unwrap();
+    let mut buf = [0x12, 0x34];
+    assert_eq!(dev.spi_transfer(&mut buf).unwrap(), &[0xa1, 0xa2]);
+    dev.close().unwrap();
+    assert!(matches!(dev, Device::Terminal(_)));
+    fake.assert_done();
+}
+
+#[test]
+fn wrong_mode() {
+    let mut fake = FakeSerial::new();
+    fake.expect(TERMINAL_RESET)
+        .reply(b"HiZ>")
+        .expect(&[0x00])
+        .reply(b"BBIO1")
+        .expect(&[0b00001111]);
+
+    // Nothing is sent for SPI operations outside of SPI mode.
+    let mut dev = device(&fake);
+    assert_request(dev.spi_transfer(&mut [0x12]));
+    dev.enter_bitbang().unwrap();
+    assert_request(dev.spi_set_speed(buspirate::spi::Speed::Speed1MHz));
+    assert_request(dev.spi_chip_select(true));
+    assert_request(dev.spi_write_then_read(&[0x03], &mut [0; 2], true));
+    assert!(matches!(dev, Device::BitBang(_)));
+    dev.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn enter_spi_failed() {
+    let mut fake = FakeSerial::new();
+    fake.expect(TERMINAL_RESET)
+        .reply(b"HiZ>")
+        .expect(&[0x00])
+        .reply(b"BBIO1")
+        // The Bus Pirate doesn't respond to the SPI mode command.
+        .expect(&[0b00000001; 10])
+        .expect(&[0b00001111]);
+
+    let mut dev = device(&fake);
+    match dev.enter_spi() {
+        Err(Error::Protocol) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    // The bit-bang mode object is kept, so the device can still be closed.
+    assert!(matches!(dev, Device::BitBang(_)));
+    dev.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn enter_bitbang_lost() {
+    let mut fake = FakeSerial::new();
+    // The Bus Pirate doesn't respond at all.
+    fake.expect(TERMINAL_RESET).expect(&[0x00; 20]);
+
+    let mut dev = device(&fake);
+    assert!(dev.enter_bitbang().is_err());
+    assert!(matches!(dev, Device::Lost));
+
+    // Every call then fails without sending anything.
+    assert_request(dev.enter_bitbang());
+    assert_request(dev.enter_spi());
+    assert_request(dev.spi_transfer(&mut [0x12]));
+    assert_request(dev.close());
+    fake.assert_done();
+}
@@ -0,0 +1,745 @@
+use buspirate::spi::{ModeObject, SPI};
+use buspirate::test_util::{FakeRx, FakeSerial, FakeTx};
+use buspirate::{bitbang::BitBang, BusPirate, EntryStage, Error, Mode};
+use embedded_hal::serial::Read;
+
+const TERMINAL_RESET: &[u8] = b"\n\n\n\n\n\n\n\n\n\n#\n";
+
+/// `script_bitbang` adds the exchange for `BusPirate::to_bitbang` to the
+/// given script, with the Bus Pirate responding to the first null byte.
+fn script_bitbang(fake: &mut FakeSerial) {
+    fake.expect(TERMINAL_RESET)
+        .reply(b"HiZ>")
+        .expect(&[0x00])
+        .reply(b"BBIO1");
+}
+
+fn script_spi(fake: &mut FakeSerial) {
+    script_bitbang(fake);
+    fake.expect(&[0b00000001]).reply(b"SPI1");
+}
+
+fn bitbang(fake: &FakeSerial) -> BitBang<FakeTx, FakeRx> {
+    let (tx, rx) = fake.split();
+    BusPirate::new(tx, rx).to_bitbang().unwrap()
+}
+
+fn spi(fake: &FakeSerial) -> SPI<FakeTx, FakeRx> {
+    bitbang(fake).to_spi().unwrap()
+}
+
+#[test]
+fn to_bitbang() {
+    let mut fake = FakeSerial::new();
+    script_bitbang(&mut fake);
+    fake.expect(&[0b00001111]);
+
+    bitbang(&fake).close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn to_bitbang_retries() {
+    let mut fake = FakeSerial::new();
+    fake.expect(TERMINAL_RESET)
+        .expect(&[0x00])
+        .expect(&[0x00])
+        .reply(b"BBIO1")
+        .expect(&[0b00001111]);
+
+    bitbang(&fake).close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn to_bitbang_no_response() {
+    let mut fake = FakeSerial::new();
+    fake.expect(TERMINAL_RESET).expect(&[0x00; 20]);
+
+    let (tx, rx) = fake.split();
+    match BusPirate::new(tx, rx).to_bitbang() {
+        Err(Error::EntryFailed {
+            stage: EntryStage::Escape,
+        }) => (),
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+    fake.assert_done();
+}
+
+#[test]
+fn to_bitbang_entry_stages() {
+    let mut fake = FakeSerial::new();
+    // The Bus Pirate responds to the escapes, but not to the reset.
+    fake.expect(&TERMINAL_RESET[..10])
+        .reply(b"HiZ>")
+        .expect(&TERMINAL_RESET[10..])
+        .expect(&[0x00; 20]);
+    // The Bus Pirate resets, but doesn't support binary mode.
+    fake.expect(TERMINAL_RESET)
+        .reply(b"HiZ>")
+        .expect(&[0x00; 20]);
+
+    let (tx, rx) = fake.split();
+    match BusPirate::new(tx, rx).to_bitbang() {
+        Err(Error::EntryFailed {
+            stage: EntryStage::Reset,
+        }) => (),
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+    let (tx, rx) = fake.split();
+    match BusPirate::new(tx, rx).to_bitbang() {
+        Err(Error::EntryFailed {
+            stage: EntryStage::BinaryEnter,
+        }) => (),
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+    fake.assert_done();
+}
+
+#[test]
+fn to_spi() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0b00001111]);
+
+    spi(&fake).close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn to_spi_wrong_version() {
+    let mut fake = FakeSerial::new();
+    script_bitbang(&mut fake);
+    for _ in 0..10 {
+        fake.expect(&[0b00000001]).reply(b"SPI2");
+    }
+    fake.expect(&[0b00001111]);
+
+    let err = match bitbang(&fake).to_spi() {
+        Ok(_) => panic!("unexpected success"),
+        Err(err) => err,
+    };
+    match err.error {
+        Error::ModeUnavailable(Mode::SPI) => (),
+        other => panic!("unexpected error {:?}", other),
+    }
+    err.previous.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn transfer_bytes() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0b00010010, 0x01, 0x02, 0x03])
+        .reply(&[0x01, 0xa1, 0xa2, 0xa3])
+        .expect(&[0b00001111]);
+
+    let mut spi = spi(&fake);
+    let mut buf = [0x01, 0x02, 0x03];
+    assert_eq!(spi.transfer_bytes(&mut buf).unwrap(), &[0xa1, 0xa2, 0xa3]);
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn transfer_bytes_empty() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0b00001111]);
+
+    // An empty transfer must not send anything to the Bus Pirate.
+    let mut spi = spi(&fake);
+    assert_eq!(spi.transfer_bytes(&mut []).unwrap(), &[]);
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn transfer_bytes_one() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0b00010000, 0x5a])
+        .reply(&[0x01, 0xa5])
+        .expect(&[0b00001111]);
+
+    let mut spi = spi(&fake);
+    assert_eq!(spi.transfer_bytes(&mut [0x5a]).unwrap(), &[0xa5]);
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn transfer_bytes_sixteen() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    let mut cmd = vec![0b00011111];
+    cmd.extend(0..16);
+    let mut resp = vec![0x01];
+    resp.extend(100..116);
+    fake.expect(&cmd).reply(&resp).expect(&[0b00001111]);
+
+    let mut spi = spi(&fake);
+    let mut buf: Vec<u8> = (0..16).collect();
+    assert_eq!(spi.transfer_bytes(&mut buf).unwrap(), &resp[1..]);
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn transfer_bytes_too_long() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0b00001111]);
+
+    let mut spi = spi(&fake);
+    match spi.transfer_bytes(&mut [0; 17]) {
+        Err(Error::Request) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn write_then_read_verify() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0b00000100, 0, 1, 0, 2, 0x03])
+        .reply(&[0x01, 0xa1, 0xa2])
+        .expect(&[0b00000001])
+        .reply(b"SPI1")
+        // The second time, a response byte is duplicated in transit.
+        .expect(&[0b00000100, 0, 1, 0, 2, 0x03])
+        .reply(&[0x01, 0xa1, 0xa1, 0xa2])
+        .expect(&[0b00000001])
+        .reply(b"SPI1")
+        .expect(&[0b00001111]);
+
+    let mut spi = spi(&fake);
+    spi.set_verify(true);
+    let mut buf = [0; 2];
+    spi.write_then_read(&[0x03], &mut buf, true).unwrap();
+    assert_eq!(buf, [0xa1, 0xa2]);
+    match spi.write_then_read(&[0x03], &mut buf, true) {
+        Err(Error::Protocol) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn transfer_byte_flush_failure() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0b00010000, 0x5a])
+        .fail_flush()
+        // The Bus Pirate receives the command anyway, and responds.
+        .reply(&[0x01, 0xa5])
+        .expect(&[0b00010000, 0x33])
+        .reply(&[0x01, 0xcc])
+        .expect(&[0b00001111]);
+
+    let mut spi = spi(&fake);
+    match spi.transfer_byte(0x5a) {
+        Err(Error::Write(_)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert_eq!(spi.transfer_byte(0x33).unwrap(), 0xcc);
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn spi_measure_voltage() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0b01100011])
+        .reply(&[0x01])
+        // Round trip through bit-bang mode to use the ADC.
+        .expect(&[0x00])
+        .reply(b"BBIO1")
+        .expect(&[0b00010100])
+        .reply(&[0x01, 0x23])
+        .expect(&[0b00000001])
+        .reply(b"SPI1")
+        // The speed is restored afterwards.
+        .expect(&[0b01100011])
+        .reply(&[0x01])
+        .expect(&[0b00001111]);
+
+    let mut spi = spi(&fake);
+    spi.set_speed(buspirate::spi::Speed::Speed1MHz).unwrap();
+    let (raw, spi) = spi.measure_voltage().unwrap();
+    assert_eq!(raw, 0x0123);
+    assert_eq!(spi.effective_hz(), Some(1_000_000));
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn spi_measure_voltage_failed() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    // The Bus Pirate never confirms the switch to bit-bang mode.
+    fake.expect(&[0x00; 20]).expect(&[0b00001111]);
+
+    let err = match spi(&fake).measure_voltage() {
+        Err(err) => err,
+        Ok(_) => panic!("measure_voltage succeeded"),
+    };
+    match err.error {
+        Error::Protocol => (),
+        other => panic!("unexpected error {:?}", other),
+    }
+    // The Bus Pirate never left SPI mode, so the original object is returned.
+    match err.previous {
+        ModeObject::SPI(spi) => spi.close().unwrap(),
+        ModeObject::BitBang(_) => panic!("returned a bit-bang mode object"),
+    };
+    fake.assert_done();
+}
+
+#[test]
+fn spi_measure_voltage_adc_failed() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0x00])
+        .reply(b"BBIO1")
+        .expect(&[0b00010100])
+        .fail_flush()
+        // Only bit-bang mode commands are sent afterwards.
+        .expect(&[0b00001111]);
+
+    let err = match spi(&fake).measure_voltage() {
+        Err(err) => err,
+        Ok(_) => panic!("measure_voltage succeeded"),
+    };
+    match err.error {
+        Error::Write(_) => (),
+        other => panic!("unexpected error {:?}", other),
+    }
+    match err.previous {
+        ModeObject::BitBang(bb) => bb.close().unwrap(),
+        ModeObject::SPI(_) => panic!("returned an SPI mode object"),
+    };
+    fake.assert_done();
+}
+
+#[test]
+fn write_then_read_partial() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    // The device responds with only two of the four requested bytes.
+    fake.expect(&[0b00000100, 0, 1, 0, 4, 0x03])
+        .reply(&[0x01, 0xa1, 0xa2])
+        .expect(&[0b00001111]);
+
+    let mut spi = spi(&fake);
+    let mut buf = [0; 4];
+    assert_eq!(
+        spi.write_then_read_partial(&[0x03], &mut buf, true, 10)
+            .unwrap(),
+        2
+    );
+    assert_eq!(buf, [0xa1, 0xa2, 0, 0]);
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn enter_generic() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0b00001111]);
+
+    let spi: SPI<_, _> = bitbang(&fake).enter().unwrap();
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn transfer_with_progress() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    let mut cmd = vec![0b00011111];
+    cmd.extend(0..16);
+    let mut resp = vec![0x01];
+    resp.extend(100..116);
+    fake.expect(&cmd)
+        .reply(&resp)
+        .expect(&[0b00010011, 16, 17, 18, 19])
+        .reply(&[0x01, 116, 117, 118, 119])
+        .expect(&[0b00001111]);
+
+    let mut spi = spi(&fake);
+    let mut buf: Vec<u8> = (0..20).collect();
+    let mut progress = Vec::new();
+    let got = spi
+        .transfer_with_progress(&mut buf, |done| progress.push(done))
+        .unwrap();
+    assert_eq!(got, (100..120).collect::<Vec<u8>>().as_slice());
+    assert_eq!(progress, [16, 20]);
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn to_bitbang_skip_drain() {
+    let mut fake = FakeSerial::new();
+    fake.expect(TERMINAL_RESET)
+        .expect(&[0x00])
+        // The Bus Pirate confirms twice, as if it received two null bytes.
+        .reply(b"BBIO1BBIO1")
+        .expect(&[0b00001111]);
+
+    let (tx, rx) = fake.split();
+    let bb = BusPirate::new(tx, rx)
+        .with_skip_drain(true)
+        .to_bitbang()
+        .unwrap();
+    // The second confirmation is left unread, rather than discarded.
+    let (_, mut rx) = bb.close().unwrap().release();
+    for want in b"BBIO1" {
+        assert_eq!(rx.read().unwrap(), *want);
+    }
+    fake.assert_done();
+}
+
+#[test]
+fn transfer_bytes_retry() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    // The acknowledgement is corrupted the first time.
+    fake.expect(&[0b00010001, 0x9f, 0x00])
+        .reply(&[0x81, 0xef])
+        .expect(&[0b00010001, 0x9f, 0x00])
+        .reply(&[0x01, 0xff, 0xef])
+        .expect(&[0b00001111]);
+
+    let mut spi = spi(&fake);
+    let mut buf = [0x9f, 0x00];
+    assert_eq!(
+        spi.transfer_bytes_retry(&mut buf, 1).unwrap(),
+        &[0xff, 0xef]
+    );
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn detect_mode() {
+    use buspirate::DetectedMode;
+
+    let mut fake = FakeSerial::new();
+    fake.expect(&[0x01])
+        .reply(b"SPI1")
+        // Later, the Bus Pirate has been reset into terminal mode.
+        .expect(&[0x01])
+        .expect(&[0x00]);
+
+    let (tx, rx) = fake.split();
+    let mut bp = BusPirate::new(tx, rx);
+    assert_eq!(bp.detect_mode().unwrap(), DetectedMode::SPI);
+    assert_eq!(bp.detect_mode().unwrap(), DetectedMode::Unknown);
+    fake.assert_done();
+}
+
+#[test]
+fn verify_link() {
+    let mut fake = FakeSerial::new();
+    script_bitbang(&mut fake);
+    for _ in 0..4 {
+        fake.expect(&[0x00]).reply(b"BBIO1");
+    }
+    fake.expect(&[0b00001111]).reply(b"RESET\r\nHiZ>");
+    // With a parity mismatch, some of the bytes are corrupted.
+    script_bitbang(&mut fake);
+    fake.expect(&[0x00])
+        .reply(b"BBIO1")
+        .expect(&[0x00])
+        .reply(b"BBIO\xb1");
+
+    let (tx, rx) = fake.split();
+    let mut bp = BusPirate::new(tx, rx);
+    bp.verify_link().unwrap();
+    match bp.verify_link() {
+        Err(Error::LinkMismatch) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    fake.assert_done();
+}
+
+#[test]
+fn into_inner() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+
+    // The Bus Pirate is left in SPI mode, so nothing more is written.
+    let (_tx, _rx) = spi(&fake).into_inner();
+    fake.assert_done();
+}
+
+#[test]
+fn close_checked() {
+    struct Delay(u32);
+    impl embedded_hal::blocking::delay::DelayMs<u16> for Delay {
+        fn delay_ms(&mut self, ms: u16) {
+            self.0 += ms as u32;
+        }
+    }
+
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0b00001111]).reply(b"RESET\r\nHiZ>");
+    // The Bus Pirate is then checked by briefly entering bit-bang mode.
+    script_bitbang(&mut fake);
+    fake.expect(&[0b00001111]).reply(b"RESET\r\nHiZ>");
+
+    let mut delay = Delay(0);
+    spi(&fake).close_checked(&mut delay, 100).unwrap();
+    assert_eq!(delay.0, 100);
+    fake.assert_done();
+}
+
+#[test]
+fn forget_reset_survives_transitions() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0x00]).reply(b"BBIO1");
+
+    let mut bb = bitbang(&fake);
+    bb.forget_reset();
+    let bb = bb.to_spi().unwrap().to_bitbang().unwrap();
+    // Dropping must not send the reset sequence, since the script doesn't
+    // expect it.
+    drop(bb);
+    fake.assert_done();
+}
+
+#[test]
+fn reset_on_drop() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0x00, 0b00001111]);
+
+    drop(spi(&fake));
+    fake.assert_done();
+}
+
+#[test]
+fn write_then_read_chunked() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    let data: Vec<u8> = (0..4100).map(|i| i as u8).collect();
+    let mut first = vec![0x01];
+    first.extend(&data[..4096]);
+    let mut second = vec![0x01];
+    second.extend(&data[4096..]);
+    fake.expect(&[0b00000010])
+        .reply(&[0x01])
+        // The first command carries the write data and reads 4096 bytes.
+        .expect(&[0b00000101, 0x00, 0x01, 0x10, 0x00, 0x03])
+        .reply(&first)
+        // The second reads the remaining four.
+        .expect(&[0b00000101, 0x00, 0x00, 0x00, 0x04])
+        .reply(&second)
+        .expect(&[0b00000011])
+        .reply(&[0x01])
+        .expect(&[0b00001111]);
+
+    let mut spi = spi(&fake);
+    let mut chunk = [0; 1000];
+    let mut got = Vec::new();
+    let mut sizes = Vec::new();
+    spi.write_then_read_chunked(&[0x03], data.len(), &mut chunk, true, |c| {
+        sizes.push(c.len());
+        got.extend_from_slice(c);
+    })
+    .unwrap();
+    assert_eq!(sizes, [1000, 1000, 1000, 1000, 100]);
+    assert_eq!(got, data);
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn write_then_read_chunked_too_long() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    // Nothing is sent, so chip select is never activated.
+    fake.expect(&[0b00001111]);
+
+    let mut spi = spi(&fake);
+    let mut chunk = [0; 16];
+    match spi.write_then_read_chunked(&[0; 4097], 1, &mut chunk, true, |_| ()) {
+        Err(Error::Request) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(!spi.config_snapshot().cs_active);
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn enter_bootloader() {
+    let mut fake = FakeSerial::new();
+    fake.expect(TERMINAL_RESET)
+        .reply(b"RESET\r\n\r\nBus Pirate v3.b\r\nHiZ>")
+        .expect(b"$\n")
+        .reply(b"Are you sure? y/n")
+        .expect(b"y");
+
+    let (tx, rx) = fake.split();
+    BusPirate::new(tx, rx).enter_bootloader().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn enter_bootloader_no_prompt() {
+    let mut fake = FakeSerial::new();
+    fake.expect(TERMINAL_RESET)
+        .reply(b"HiZ>")
+        .expect(b"$\n")
+        .reply(b"Syntax error\r\nHiZ>");
+
+    // The confirmation is never sent.
+    let (tx, rx) = fake.split();
+    match BusPirate::new(tx, rx).enter_bootloader() {
+        Err(Error::Protocol) => (),
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+    fake.assert_done();
+}
+
+#[test]
+fn to_bitbang_with_banner_late() {
+    let mut fake = FakeSerial::new();
+    // Only the start of the banner has arrived when the reset completes, and
+    // the rest arrives while the null bytes are being sent.
+    fake.expect(TERMINAL_RESET)
+        .reply(b"RESET\r\n")
+        .expect(&[0x00])
+        .reply(b"Bus Pirate v3.5\r\n")
+        .expect(&[0x00])
+        .reply(b"HiZ>")
+        .expect(&[0x00])
+        .reply(b"BBIO1")
+        .expect(&[0b00001111]);
+
+    let (tx, rx) = fake.split();
+    let mut banner = [0; 64];
+    let (bb, len) = BusPirate::new(tx, rx)
+        .to_bitbang_with_banner(&mut banner)
+        .unwrap();
+    assert_eq!(&banner[..len], b"RESET\r\nBus Pirate v3.5\r\nHiZ>");
+    bb.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn close_checked_no_response() {
+    struct Delay;
+    impl embedded_hal::blocking::delay::DelayMs<u16> for Delay {
+        fn delay_ms(&mut self, _: u16) {}
+    }
+
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    fake.expect(&[0b00001111]);
+    // The Bus Pirate doesn't respond to the check at all.
+    fake.expect(TERMINAL_RESET).expect(&[0x00; 20]);
+
+    let err = spi(&fake).close_checked(&mut Delay, 100).unwrap_err();
+    match err.error {
+        Error::Timeout => (),
+        other => panic!("unexpected error {:?}", other),
+    }
+    // The serial channels are still available.
+    let _ = err.previous.release();
+    fake.assert_done();
+}
+
+#[test]
+fn has_pending_keeps_byte() {
+    let mut fake = FakeSerial::new();
+    script_spi(&mut fake);
+    // The response arrives early, so that it can be peeked before the
+    // command that reads it.
+    fake.reply(&[0x01, 0x55])
+        .expect(&[0b00010000, 0xaa])
+        .expect(&[0b00001111]);
+
+    // Skipping the drain after mode entry keeps the early response.
+    let (tx, rx) = fake.split();
+    let mut spi = BusPirate::new(tx, rx)
+        .with_skip_drain(true)
+        .to_bitbang()
+        .unwrap()
+        .to_spi()
+        .unwrap();
+    // A lost byte would otherwise make the transfer wait forever.
+    spi.set_read_timeout(Some(100));
+    assert!(spi.has_pending().unwrap());
+    assert!(spi.has_pending().unwrap());
+    assert_eq!(spi.transfer_byte(0xaa).unwrap(), 0x55);
+    assert!(!spi.has_pending().unwrap());
+    spi.close().unwrap();
+    fake.assert_done();
+}
+
+#[test]
+fn map_serial_error() {
+    use buspirate::MapSerialError;
+    use embedded_hal::serial::Write;
+
+    #[derive(Debug, PartialEq)]
+    enum LinkError {
+        Tx,
+        Rx,
+    }
+
+    /// `LinkTx` reports `FakeTx` errors as its own error type.
+    struct LinkTx(FakeTx);
+    impl Write<u8> for LinkTx {
+        type Error = LinkError;
+
+        fn write(&mut self, word: u8) -> nb::Result<(), LinkError> {
+            self.0.write(word).map_err(|e| e.map(|_| LinkError::Tx))
+        }
+
+        fn flush(&mut self) -> nb::Result<(), LinkError> {
+            self.0.flush().map_err(|e| e.map(|_| LinkError::Tx))
+        }
+    }
+
+    /// `LinkRx` fails whenever there's nothing to read.
+    struct LinkRx(FakeRx);
+    impl Read<u8> for LinkRx {
+        type Error = LinkError;
+
+        fn read(&mut self) -> nb::Result<u8, LinkError> {
+            match self.0.read() {
+                Ok(c) => Ok(c),
+                Err(_) => Err(nb::Error::Other(LinkError::Rx)),
+            }
+        }
+    }
+
+    let mut fake = FakeSerial::new();
+    fake.expect(b"i").fail_flush();
+    let (tx, rx) = fake.split();
+    let (mut tx, mut rx) = (LinkTx(tx), LinkRx(rx));
+
+    nb::block!(tx.write(b'i')).map_tx::<LinkError>().unwrap();
+    match nb::block!(tx.flush()).map_tx::<LinkError>() {
+        Err(Error::Write(LinkError::Tx)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    match nb::block!(rx.read()).map_rx::<LinkError>() {
+        Err(Error::Read(LinkError::Rx)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    fake.assert_done();
+}
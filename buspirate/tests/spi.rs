@@ -0,0 +1,66 @@
+//! End-to-end test of a typical SPI session, from terminal mode to SPI mode
+//! and back, against a scripted fake serial port.
+//!
+//! Tests for other modes should follow the same shape: script every byte
+//! exchanged for a complete session, run the session, and then check that
+//! the whole script was followed.
+
+use buspirate::spi::{self, Comms};
+use buspirate::test_util::FakeSerial;
+use buspirate::BusPirate;
+
+#[test]
+fn round_trip() {
+    let mut fake = FakeSerial::new();
+    fake
+        // Reset from terminal mode into bit-bang mode.
+        .expect(&[b'\n'; 10])
+        .expect(b"#\n")
+        .reply(b"RESET\r\nBus Pirate v3.b\r\nHiZ>")
+        .expect(&[0x00])
+        .reply(b"BBIO1")
+        // Enter SPI mode.
+        .expect(&[0b00000001])
+        .reply(b"SPI1")
+        // set_speed(Speed1MHz)
+        .expect(&[0b01100011])
+        .reply(&[0x01])
+        // set_config with 3.3V outputs and otherwise the defaults.
+        .expect(&[0b10001010])
+        .reply(&[0x01])
+        // chip_select(true)
+        .expect(&[0b00000010])
+        .reply(&[0x01])
+        // transfer_bytes of a JEDEC ID read.
+        .expect(&[0b00010011, 0x9f, 0x00, 0x00, 0x00])
+        .reply(&[0x01, 0xff, 0xef, 0x40, 0x18])
+        // chip_select(false)
+        .expect(&[0b00000011])
+        .reply(&[0x01])
+        // Reset back to terminal mode.
+        .expect(&[0b00001111]);
+
+    let (tx, rx) = fake.split();
+    let bb = BusPirate::new(tx, rx).to_bitbang().unwrap();
+    let mut bp_spi = bb.to_spi().unwrap();
+    bp_spi.set_speed(spi::Speed::Speed1MHz).unwrap();
+    bp_spi
+        .set_config(spi::Config {
+            pin_output: spi::PinOutput::PinOutput3_3V,
+            ..spi::DEFAULT_CONFIG
+        })
+        .unwrap();
+
+    let mut buf = [0x9f, 0x00, 0x00, 0x00];
+    let id = bp_spi
+        .transaction_cs(|bp_spi| bp_spi.transfer_bytes(&mut buf).map(|got| got.to_vec()))
+        .unwrap();
+    assert_eq!(id, [0xff, 0xef, 0x40, 0x18]);
+
+    let snapshot = bp_spi.config_snapshot();
+    assert_eq!(snapshot.speed, Some(spi::Speed::Speed1MHz));
+    assert!(!snapshot.cs_active);
+
+    bp_spi.close().unwrap();
+    fake.assert_done();
+}